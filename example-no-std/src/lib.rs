@@ -0,0 +1,19 @@
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::String;
+use lugnut::{GenerationError, Hotp, Totp};
+
+/// Writes the six digit HOTP code for `counter` into `buf` without allocating the token.
+pub fn hotp_code(key: String, counter: u64, buf: &mut [u8; 6]) -> Result<&str, GenerationError> {
+    Hotp::new().generate_into(key, counter, buf)
+}
+
+/// Generates the TOTP code for a Unix time read from the device's own clock, since there is no
+/// system clock without `std`.
+pub fn totp_code(key: String, unix_time: u64) -> Result<String, GenerationError> {
+    let mut totp = Totp::new();
+    totp.with_time(unix_time);
+    totp.generate(key)
+}
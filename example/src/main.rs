@@ -3,6 +3,6 @@ use lugnut::{Totp, Hotp};
 fn main() {
     let totp = Totp::new();
     let hotp = Hotp::new();
-    println!("{:?}", totp.generate("my key".to_string()));
-    println!("{:?}", hotp.generate("my key".to_string(), 100));
+    println!("{:?}", totp.generate("my super secret key".to_string()));
+    println!("{:?}", hotp.generate("my super secret key".to_string(), 100));
 }
@@ -1,168 +1,769 @@
-use crate::{digest, generate_otp, verify_delta, Algorithm, GenerationError};
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+#[cfg(feature = "qrcode")]
+use crate::otpauth::generate_qr_svg;
+#[cfg(feature = "std")]
+use crate::otpauth::OtpAuthUrlBuilder;
+use crate::secret::Secret;
+use crate::{
+    check_key_length, check_token_length, digest_bytes, find_delta, generate_otp,
+    generate_otp_into, numeric_token, verify_delta, Algorithm, Counter, GenerationError, Otp,
+    DEFAULT_MIN_KEY_LENGTH,
+};
+
+/// Derives the HMAC key from the shared secret, see `Hotp::with_key_transform`.
+pub type KeyTransform = Box<dyn Fn(&[u8]) -> Vec<u8> + Send + Sync>;
 
 pub struct Hotp {
     window: u64,
+    window_back: u64,
     digits: u32,
+    algorithm: Algorithm,
     digest: Vec<u8>,
+    secret: Secret,
+    min_key_length: usize,
+    key_transform: Option<KeyTransform>,
 }
 impl Hotp {
     pub fn new() -> Hotp {
         Hotp {
             window: 0,
+            window_back: 0,
             digits: 6,
+            algorithm: Algorithm::Sha1,
             digest: Vec::new(),
+            secret: Secret::default(),
+            min_key_length: DEFAULT_MIN_KEY_LENGTH,
+            key_transform: None,
         }
     }
-    pub fn with_length<'a>(&'a mut self, n: u32) -> &'a mut Hotp {
+    /// Set the number of digits in the generated code, between 1 and `MAX_DIGITS`.
+    /// A code can't carry more entropy than the 31-bit integer it is truncated from, so
+    /// out of range lengths fail with `GenerationError::InvalidDigits` when generating.
+    pub fn with_length(&mut self, n: u32) -> &mut Hotp {
         self.digits = n;
         self
     }
-    pub fn with_digest<'a>(&'a mut self, digest: Vec<u8>) -> &'a mut Hotp {
+    /// Set the HMAC algorithm, defaults to SHA1 as in RFC 4226.
+    pub fn with_algorithm(&mut self, algorithm: Algorithm) -> &mut Hotp {
+        self.algorithm = algorithm;
+        self
+    }
+    /// Use a self-generated digest of at least `MIN_DIGEST_LENGTH` bytes instead of hashing the key.
+    pub fn with_digest(&mut self, digest: Vec<u8>) -> &mut Hotp {
         self.digest = digest;
         self
     }
-    pub fn with_window<'a>(&'a mut self, window: u64) -> &'a mut Hotp {
+    pub fn with_window(&mut self, window: u64) -> &mut Hotp {
         self.window = window;
         self
     }
-    pub fn generate<'a>(
-        &'a self,
-        key: String,
-        counter: u128,
-    ) -> std::result::Result<String, GenerationError> {
-        let hash = if self.digest.is_empty() {
-            digest(key.clone(), counter, Algorithm::Sha1)?
-        } else {
-            self.digest.clone()
-        };
+    /// Also accept codes up to `n` counters behind the one passed to `verify`, so the scanned
+    /// range becomes `counter - n ..= counter + window`. Defaults to 0 (scan forward only).
+    pub fn with_window_back(&mut self, n: u64) -> &mut Hotp {
+        self.window_back = n;
+        self
+    }
+    /// Derive the HMAC key from the shared secret, e.g. for servers that hash or truncate the
+    /// secret before keying. Applied after the `with_min_key_length` check, by both generate
+    /// and verify. Defaults to using the secret as is.
+    pub fn with_key_transform(&mut self, transform: KeyTransform) -> &mut Hotp {
+        self.key_transform = Some(transform);
+        self
+    }
+    /// Set the minimum accepted key length in bytes. Shorter keys fail with
+    /// `GenerationError::KeyTooShort`. Defaults to `DEFAULT_MIN_KEY_LENGTH`.
+    pub fn with_min_key_length(&mut self, min: usize) -> &mut Hotp {
+        self.min_key_length = min;
+        self
+    }
+    /// Use a hex encoded secret (e.g. the RFC 4226 test vectors) instead of the `key` passed to
+    /// `generate`/`verify`. The hex is decoded to raw key bytes before keying the HMAC.
+    pub fn with_secret_hex(
+        &mut self,
+        hex: &str,
+    ) -> core::result::Result<&mut Hotp, GenerationError> {
+        self.secret = hex::decode(hex)
+            .map_err(|_| GenerationError::InvalidHexSecret())?
+            .into();
+        Ok(self)
+    }
+    pub fn generate(
+        &self,
+        key: impl AsRef<str>,
+        counter: u64,
+    ) -> core::result::Result<String, GenerationError> {
+        let hash = self.hash(key.as_ref(), Counter(counter))?;
         generate_otp(self.digits, hash)
     }
-    pub fn verify<'a>(
-        &'a self,
-        token: String,
-        key: String,
-        counter: u128,
-    ) -> std::result::Result<bool, GenerationError> {
-        let hash = if self.digest.is_empty() {
-            digest(key.clone(), counter, Algorithm::Sha1)?
+    pub fn generate_into<'b>(
+        &self,
+        key: impl AsRef<str>,
+        counter: u64,
+        buf: &'b mut [u8],
+    ) -> core::result::Result<&'b str, GenerationError> {
+        let hash = self.hash(key.as_ref(), Counter(counter))?;
+        generate_otp_into(self.digits, &hash, buf)
+    }
+    /// Generate `count` consecutive codes starting at `start`, each paired with its counter,
+    /// e.g. to print a sheet of look-ahead codes when provisioning a device. Fails with
+    /// `GenerationError::CounterOverflow` if the last counter would be past `u64::MAX`.
+    pub fn generate_range(
+        &self,
+        key: impl AsRef<str>,
+        start: u64,
+        count: u32,
+    ) -> core::result::Result<Vec<(u64, String)>, GenerationError> {
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        let end = start
+            .checked_add(count as u64 - 1)
+            .ok_or(GenerationError::CounterOverflow())?;
+        (start..=end)
+            .map(|counter| Ok((counter, self.generate(key.as_ref(), counter)?)))
+            .collect()
+    }
+    pub fn verify(
+        &self,
+        token: impl AsRef<str>,
+        key: impl AsRef<str>,
+        counter: u64,
+    ) -> core::result::Result<bool, GenerationError> {
+        verify_delta(
+            token.as_ref(),
+            Counter(counter.saturating_sub(self.window_back)),
+            Counter(counter.saturating_add(self.window)),
+            self.digits,
+            |c| self.hash(key.as_ref(), c),
+        )
+    }
+    /// Like `verify`, but returns the signed offset of the matched counter from `counter`, or
+    /// `None` if nothing in the window matched. A positive delta means the client is ahead,
+    /// e.g. it generated codes that were never submitted, so the stored counter can be resynced.
+    pub fn verify_with_delta(
+        &self,
+        token: impl AsRef<str>,
+        key: impl AsRef<str>,
+        counter: u64,
+    ) -> core::result::Result<Option<i64>, GenerationError> {
+        find_delta(
+            token.as_ref(),
+            Counter(counter),
+            self.window_back,
+            self.window,
+            self.digits,
+            |c| self.hash(key.as_ref(), c),
+        )
+    }
+    /// Verify `token` at exactly `counter`, ignoring the window, e.g. to check a slot recorded
+    /// by a replay protection store.
+    pub fn verify_at(
+        &self,
+        token: impl AsRef<str>,
+        key: impl AsRef<str>,
+        counter: u64,
+    ) -> core::result::Result<bool, GenerationError> {
+        let counter = Counter(counter);
+        verify_delta(token.as_ref(), counter, counter, self.digits, |c| {
+            self.hash(key.as_ref(), c)
+        })
+    }
+    /// Verify a code that was stored as an integer, which drops leading zeros. The code is
+    /// zero padded to the configured length before comparing.
+    pub fn verify_numeric(
+        &self,
+        code: u32,
+        key: impl AsRef<str>,
+        counter: u64,
+    ) -> core::result::Result<bool, GenerationError> {
+        self.verify(numeric_token(code, self.digits), key, counter)
+    }
+    /// Like `verify`, but a token of the wrong length is reported as
+    /// `GenerationError::TokenLengthMismatch` instead of a failed verification.
+    pub fn verify_strict(
+        &self,
+        token: impl AsRef<str>,
+        key: impl AsRef<str>,
+        counter: u64,
+    ) -> core::result::Result<bool, GenerationError> {
+        check_token_length(token.as_ref(), self.digits)?;
+        self.verify(token, key, counter)
+    }
+    /// Build the `otpauth://hotp/...` Key URI for enrolling this HOTP configuration, with the
+    /// Base32 encoded secret and the given initial `counter`.
+    #[cfg(feature = "std")]
+    pub fn to_otpauth_url(&self, label: &str, issuer: &str, secret: &str, counter: u64) -> String {
+        self.otpauth_url_builder(counter)
+            .build(label, issuer, secret)
+    }
+    /// Like `to_otpauth_url`, but using the secret stored with `with_secret_hex`, so the URL
+    /// can't be built with a different secret than codes are generated with. Fails with
+    /// `GenerationError::MissingSecret` if no secret is stored.
+    #[cfg(feature = "std")]
+    pub fn otpauth_url_for(
+        &self,
+        label: &str,
+        issuer: &str,
+        counter: u64,
+    ) -> core::result::Result<String, GenerationError> {
+        if self.secret.is_empty() {
+            return Err(GenerationError::MissingSecret());
+        }
+        Ok(self.otpauth_url_builder(counter).build_from_bytes(
+            label,
+            issuer,
+            self.secret.as_bytes(),
+        ))
+    }
+    #[cfg(feature = "qrcode")]
+    pub fn otpauth_qr_svg(
+        &self,
+        label: &str,
+        issuer: &str,
+        secret: &str,
+        counter: u64,
+    ) -> core::result::Result<String, GenerationError> {
+        generate_qr_svg(&self.to_otpauth_url(label, issuer, secret, counter))
+    }
+    #[cfg(feature = "std")]
+    #[doc(hidden)]
+    fn otpauth_url_builder(&self, counter: u64) -> OtpAuthUrlBuilder {
+        use alloc::string::ToString;
+
+        let mut builder = OtpAuthUrlBuilder::new("hotp");
+        builder
+            .with_parameter("algorithm", &self.algorithm.to_string())
+            .with_parameter("digits", &self.digits.to_string())
+            .with_parameter("counter", &counter.to_string());
+        builder
+    }
+    #[doc(hidden)]
+    fn hash(&self, key: &str, counter: Counter) -> core::result::Result<Vec<u8>, GenerationError> {
+        if !self.digest.is_empty() {
+            return Ok(self.digest.clone());
+        }
+        let key = if self.secret.is_empty() {
+            key.as_bytes()
         } else {
-            self.digest.clone()
+            self.secret.as_bytes()
         };
-        verify_delta(token, counter, self.digits, self.window, hash)
+        check_key_length(key, self.min_key_length)?;
+        match &self.key_transform {
+            Some(transform) => {
+                // Held as a `Secret` so the derived key is wiped like the stored one
+                let derived = Secret::from(transform(key));
+                digest_bytes(derived.as_bytes(), counter, self.algorithm)
+            }
+            None => digest_bytes(key, counter, self.algorithm),
+        }
     }
 }
 
-#[cfg(test)]
+impl Otp for Hotp {
+    fn generate(&self, key: &str, factor: u64) -> core::result::Result<String, GenerationError> {
+        Hotp::generate(self, key, factor)
+    }
+    fn verify(
+        &self,
+        token: &str,
+        key: &str,
+        factor: u64,
+    ) -> core::result::Result<bool, GenerationError> {
+        Hotp::verify(self, token, key, factor)
+    }
+}
+
+impl Default for Hotp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
 mod tests_generate {
     use crate::generate_secret;
     use crate::hotp::Hotp;
+    use crate::GenerationError;
 
     #[test]
     fn test_generate_hotp_default() {
         let key = generate_secret();
         let hotp = Hotp::new();
-        let pad = match hotp.generate(key, 100) {
-            Ok(h) => h,
-            _ => String::from(""),
-        };
+        let pad = hotp.generate(key, 100).unwrap_or_default();
         assert_eq!(pad.len(), 6);
     }
 
     #[test]
     fn test_generate_hotp_custom_length() {
+        let key = generate_secret();
+        let mut hotp = Hotp::new();
+        hotp.with_length(8);
+        let pad = hotp.generate(key, 100).unwrap_or_default();
+        assert_eq!(pad.len(), 8);
+    }
+
+    #[test]
+    fn test_generate_hotp_invalid_length() {
         let key = generate_secret();
         let mut hotp = Hotp::new();
         hotp.with_length(50);
-        let pad = match hotp.generate(key, 100) {
-            Ok(h) => h,
-            _ => String::from(""),
-        };
-        assert_eq!(pad.len(), 50);
+        assert_eq!(
+            hotp.generate(key.clone(), 100),
+            Err(GenerationError::InvalidDigits(50))
+        );
+        hotp.with_length(0);
+        assert_eq!(
+            hotp.generate(key, 100),
+            Err(GenerationError::InvalidDigits(0))
+        );
+    }
+
+    #[test]
+    fn test_generate_hotp_short_digest() {
+        let key = generate_secret();
+        let mut hotp = Hotp::new();
+        hotp.with_digest(vec![1, 2, 3, 4]);
+        assert_eq!(
+            hotp.generate(key, 100),
+            Err(GenerationError::InvalidDigestLength(4))
+        );
     }
 }
 
 #[cfg(test)]
 mod tests_verify {
+    use alloc::boxed::Box;
+    use alloc::format;
+    use alloc::string::String;
+
     use crate::hotp::Hotp;
-    use crate::{digest, Algorithm};
+    use crate::{digest, Algorithm, Counter};
 
     #[test]
     fn test_verify() {
-        let key = String::from("SuperSecretKey"); // Generates a otp of 0897822634
+        let key = String::from("SuperDuperSecretKey"); // Generates a otp of 0968805563 at counter 100
         let counter = 100;
         let digits = 10;
-        let defined_digest = if let Ok(d) = digest(key.clone(), counter, Algorithm::Sha1) {
-            d
-        } else {
-            vec![]
-        };
+        let defined_digest =
+            digest(key.clone(), Counter(counter), Algorithm::Sha1).unwrap_or_default();
         let mut hotp = Hotp::new();
         hotp.with_length(digits);
         hotp.with_digest(defined_digest.clone());
-        let pad = match hotp.generate(key.clone(), 100) {
-            Ok(h) => h,
-            _ => String::from(""),
-        };
-        let verified = if let Ok(v) = hotp.verify(pad, key, 100) {
-            v
-        } else {
-            false
-        };
-        assert_eq!(true, verified);
+        let pad = hotp.generate(key.clone(), 100).unwrap_or_default();
+        let verified = hotp.verify(pad, key, 100).unwrap_or_default();
+        assert!(verified);
+    }
+
+    #[test]
+    fn test_verify_at_is_exact() {
+        let key = String::from("SuperDuperSecretKey");
+        let mut hotp = Hotp::new();
+        hotp.with_window(5).with_window_back(5);
+        let pad = hotp.generate(key.clone(), 100).unwrap();
+        assert!(hotp.verify_at(&pad, key.clone(), 100).unwrap());
+        assert!(!hotp.verify_at(&pad, key.clone(), 99).unwrap());
+        assert!(!hotp.verify_at(&pad, key.clone(), 101).unwrap());
+        assert!(hotp.verify(&pad, key, 101).unwrap());
+    }
+
+    #[test]
+    fn test_verify_with_separators() {
+        let key = String::from("SuperDuperSecretKey");
+        let hotp = Hotp::new();
+        let pad = hotp.generate(key.clone(), 100).unwrap();
+        let spaced = format!("{} {}", &pad[..3], &pad[3..]);
+        let hyphenated = format!("{}-{}", &pad[..3], &pad[3..]);
+        assert!(hotp.verify(&pad, key.clone(), 100).unwrap());
+        assert!(hotp.verify(&spaced, key.clone(), 100).unwrap());
+        assert!(hotp.verify_strict(&hyphenated, key.clone(), 100).unwrap());
+        assert!(!hotp
+            .verify(format!("{}_{}", &pad[..3], &pad[3..]), key, 100)
+            .unwrap());
+    }
+
+    #[test]
+    fn test_verify_window_back() {
+        let key = String::from("SuperDuperSecretKey");
+        let mut hotp = Hotp::new();
+        let pad = hotp.generate(key.clone(), 98).unwrap();
+        assert!(!hotp.verify(pad.clone(), key.clone(), 100).unwrap());
+        hotp.with_window_back(3);
+        assert!(hotp.verify(pad, key, 100).unwrap());
+    }
+
+    #[test]
+    fn test_verify_numeric() {
+        let key = "12345678901234567890";
+        let hotp = Hotp::new();
+        assert_eq!(hotp.generate(key, 36).unwrap(), "003784");
+        assert!(!hotp.verify("3784", key, 36).unwrap());
+        assert!(hotp.verify_numeric(3784, key, 36).unwrap());
+        assert!(!hotp.verify_numeric(3784, key, 35).unwrap());
+    }
+
+    #[test]
+    fn test_key_transform() {
+        let key = String::from("SuperDuperSecretKey");
+        let mut hotp = Hotp::new();
+        hotp.with_key_transform(Box::new(|key| key.to_ascii_uppercase()));
+        let pad = hotp.generate(key.clone(), 100).unwrap();
+        assert_eq!(
+            pad,
+            Hotp::new().generate("SUPERDUPERSECRETKEY", 100).unwrap()
+        );
+        assert_ne!(pad, Hotp::new().generate(key.clone(), 100).unwrap());
+        assert!(hotp.verify(&pad, key.clone(), 100).unwrap());
+        assert!(!Hotp::new().verify(&pad, key, 100).unwrap());
+    }
+
+    #[test]
+    fn test_hotp_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Hotp>();
+    }
+
+    #[test]
+    fn test_verify_with_delta() {
+        let key = String::from("SuperDuperSecretKey");
+        let mut hotp = Hotp::new();
+        hotp.with_window(5).with_window_back(2);
+        let ahead = hotp.generate(key.clone(), 103).unwrap();
+        let behind = hotp.generate(key.clone(), 98).unwrap();
+        let outside = hotp.generate(key.clone(), 106).unwrap();
+        assert_eq!(
+            hotp.verify_with_delta(&ahead, key.clone(), 100),
+            Ok(Some(3))
+        );
+        assert_eq!(
+            hotp.verify_with_delta(&behind, key.clone(), 100),
+            Ok(Some(-2))
+        );
+        assert_eq!(hotp.verify_with_delta(&outside, key, 100), Ok(None));
+    }
+}
+
+#[cfg(test)]
+mod tests_generate_range {
+    use crate::hotp::Hotp;
+    use crate::GenerationError;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_generate_range() {
+        let key = "SuperDuperSecretKey";
+        let hotp = Hotp::new();
+        let codes = hotp.generate_range(key, 100, 3).unwrap();
+        assert_eq!(codes.len(), 3);
+        for (i, (counter, code)) in codes.into_iter().enumerate() {
+            assert_eq!(counter, 100 + i as u64);
+            assert_eq!(code, hotp.generate(key, counter).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_generate_range_at_max_counter() {
+        let key = "SuperDuperSecretKey";
+        let hotp = Hotp::new();
+        let codes = hotp.generate_range(key, u64::MAX - 1, 2).unwrap();
+        assert_eq!(
+            codes.iter().map(|(c, _)| *c).collect::<Vec<_>>(),
+            [u64::MAX - 1, u64::MAX]
+        );
+        assert_eq!(
+            hotp.generate_range(key, u64::MAX, 3),
+            Err(GenerationError::CounterOverflow())
+        );
+        assert_eq!(hotp.generate_range(key, u64::MAX, 0), Ok(Vec::new()));
     }
 }
 
 #[cfg(test)]
 mod test_builder_pattern {
+    use alloc::string::String;
+
     use crate::hotp::Hotp;
 
     #[test]
     fn test_builder_pattern_default() {
-        let key = String::from("SuperSecretKey");
+        let key = String::from("SuperDuperSecretKey");
         let counter = 100;
         let hotp = Hotp::new();
-        let pad = match hotp.generate(key, counter) {
-            Ok(h) => h,
-            _ => String::from(""),
-        };
+        let pad = hotp.generate(key, counter).unwrap_or_default();
         assert_eq!(pad.len(), 6);
     }
 
     #[test]
     fn test_builder_pattern_n_length() {
-        let key = String::from("SuperSecretKey");
+        let key = String::from("SuperDuperSecretKey");
         let counter = 100;
         let mut hotp = Hotp::new();
         hotp.with_length(10);
-        let pad = match hotp.generate(key, counter) {
-            Ok(h) => h,
-            _ => String::from(""),
-        };
+        let pad = hotp.generate(key, counter).unwrap_or_default();
         assert_eq!(pad.len(), 10);
     }
 
     #[test]
     fn test_builder_pattern_verify() {
-        let key = String::from("SuperSecretKey"); // Generates a otp of 0897822634
+        let key = String::from("SuperDuperSecretKey"); // Generates a otp of 0968805563 at counter 100
         let counter = 100;
         let mut hotp = Hotp::new();
         hotp.with_length(10);
-        let pad = match hotp.generate(key.clone(), counter) {
-            Ok(h) => h,
-            _ => String::from(""),
-        };
-        let result_correct = if let Ok(v) = hotp.verify(pad, key.clone(), counter) {
-            v
-        } else {
-            false
-        };
-        let result_fail =
-            if let Ok(v) = hotp.verify(String::from("This should not verify"), key, counter) {
-                v
-            } else {
-                false
-            };
-        assert_eq!(true, result_correct);
-        assert_eq!(false, result_fail);
+        let pad = hotp.generate(key.clone(), counter).unwrap_or_default();
+        assert_eq!(pad, "0968805563");
+        let result_correct = hotp.verify(pad, key.clone(), counter).unwrap_or_default();
+        let result_fail = hotp
+            .verify(String::from("This should not verify"), key, counter)
+            .unwrap_or_default();
+        assert!(result_correct);
+        assert!(!result_fail);
+    }
+
+    #[test]
+    fn test_builder_pattern_verify_str() {
+        let hotp = Hotp::new();
+        let pad = hotp
+            .generate("SuperDuperSecretKey", 100)
+            .unwrap_or_default();
+        assert!(hotp
+            .verify(&pad, "SuperDuperSecretKey", 100)
+            .unwrap_or_default());
+        assert!(!hotp
+            .verify("123", "SuperDuperSecretKey", 100)
+            .unwrap_or_default());
+    }
+}
+
+#[cfg(test)]
+mod tests_generate_into {
+    use alloc::string::String;
+
+    use crate::hotp::Hotp;
+    use crate::GenerationError;
+
+    #[test]
+    fn test_generate_into_matches_generate() {
+        let key = String::from("SuperDuperSecretKey");
+        let mut hotp = Hotp::new();
+        hotp.with_length(8);
+        let mut buf = [0u8; 10];
+        for counter in 0..50 {
+            let owned = hotp
+                .generate(key.clone(), counter)
+                .expect("generate failed");
+            let borrowed = hotp
+                .generate_into(key.clone(), counter, &mut buf)
+                .expect("generate_into failed");
+            assert_eq!(owned, borrowed);
+        }
+    }
+
+    #[test]
+    fn test_generate_into_buffer_too_small() {
+        let key = String::from("SuperDuperSecretKey");
+        let hotp = Hotp::new();
+        let mut buf = [0u8; 4];
+        match hotp.generate_into(key, 100, &mut buf) {
+            Err(GenerationError::BufferTooSmall(needed)) => assert_eq!(needed, 6),
+            _ => panic!("expected a BufferTooSmall error"),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests_otpauth {
+    use crate::hotp::Hotp;
+    use crate::GenerationError;
+
+    #[test]
+    fn test_to_otpauth_url() {
+        let hotp = Hotp::new();
+        let url = hotp.to_otpauth_url("alice@example.com", "Example", "SuperDuperSecretKey", 50);
+        assert_eq!(
+            url,
+            "otpauth://hotp/Example:alice%40example.com?secret=KN2XAZLSIR2XAZLSKNSWG4TFORFWK6I\
+             &issuer=Example&algorithm=SHA1&digits=6&counter=50"
+        );
+    }
+
+    #[test]
+    fn test_otpauth_url_for_stored_secret() {
+        let mut hotp = Hotp::new();
+        assert_eq!(
+            hotp.otpauth_url_for("alice@example.com", "Example", 50),
+            Err(GenerationError::MissingSecret())
+        );
+        hotp.with_secret_hex(&hex::encode("SuperDuperSecretKey"))
+            .unwrap();
+        assert_eq!(
+            hotp.otpauth_url_for("alice@example.com", "Example", 50),
+            Ok(hotp.to_otpauth_url("alice@example.com", "Example", "SuperDuperSecretKey", 50))
+        );
+    }
+}
+
+#[cfg(all(test, feature = "qrcode"))]
+mod tests_qr {
+    use crate::hotp::Hotp;
+
+    #[test]
+    fn test_otpauth_qr_svg() {
+        let hotp = Hotp::new();
+        let svg = hotp
+            .otpauth_qr_svg("alice@example.com", "Example", "SuperDuperSecretKey", 100)
+            .expect("failed to render qr code");
+        assert!(!svg.is_empty());
+        assert!(svg.contains("<svg"));
+    }
+}
+
+#[cfg(test)]
+mod tests_secret_hex {
+    use alloc::string::String;
+
+    use crate::hotp::Hotp;
+    use crate::GenerationError;
+
+    // RFC 4226 Appendix D, the ASCII secret "12345678901234567890"
+    const RFC_SECRET_HEX: &str = "3132333435363738393031323334353637383930";
+
+    #[test]
+    fn test_secret_hex_matches_rfc_vectors() {
+        let mut hotp = Hotp::new();
+        hotp.with_secret_hex(RFC_SECRET_HEX).expect("valid hex");
+        let expected = ["755224", "287082", "359152", "969429", "338314"];
+        for (counter, code) in expected.iter().enumerate() {
+            let pad = hotp
+                .generate(String::new(), counter as u64)
+                .expect("generate failed");
+            assert_eq!(&pad, code);
+        }
+    }
+
+    #[test]
+    fn test_secret_hex_invalid() {
+        let mut hotp = Hotp::new();
+        assert!(matches!(
+            hotp.with_secret_hex("not hex"),
+            Err(GenerationError::InvalidHexSecret())
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests_min_key_length {
+    use crate::hotp::Hotp;
+    use crate::GenerationError;
+
+    #[test]
+    fn test_short_key_errors_by_default() {
+        let hotp = Hotp::new();
+        assert_eq!(
+            hotp.generate("abcd", 100),
+            Err(GenerationError::KeyTooShort { got: 4, min: 16 })
+        );
+        assert_eq!(
+            hotp.verify("123456", "abcd", 100),
+            Err(GenerationError::KeyTooShort { got: 4, min: 16 })
+        );
+    }
+
+    #[test]
+    fn test_lowered_min_key_length() {
+        let mut hotp = Hotp::new();
+        hotp.with_min_key_length(4);
+        let pad = hotp.generate("abcd", 100).expect("generate failed");
+        assert_eq!(hotp.verify(&pad, "abcd", 100), Ok(true));
+    }
+
+    #[test]
+    fn test_empty_key_errors() {
+        let mut hotp = Hotp::new();
+        assert_eq!(hotp.generate("", 100), Err(GenerationError::EmptyKey()));
+        hotp.with_min_key_length(0);
+        assert_eq!(hotp.generate("", 100), Err(GenerationError::EmptyKey()));
+        assert_eq!(
+            hotp.verify("123456", "", 100),
+            Err(GenerationError::EmptyKey())
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests_verify_strict {
+    use crate::hotp::Hotp;
+    use crate::GenerationError;
+
+    const KEY: &str = "SuperDuperSecretKey";
+
+    #[test]
+    fn test_verify_lenient_wrong_length() {
+        let hotp = Hotp::new();
+        assert_eq!(hotp.verify("1234", KEY, 100), Ok(false));
+    }
+
+    #[test]
+    fn test_verify_strict_wrong_length() {
+        let hotp = Hotp::new();
+        assert_eq!(
+            hotp.verify_strict("1234", KEY, 100),
+            Err(GenerationError::TokenLengthMismatch {
+                expected: 6,
+                got: 4
+            })
+        );
+    }
+
+    #[test]
+    fn test_verify_strict_right_length() {
+        let hotp = Hotp::new();
+        let pad = hotp.generate(KEY, 100).expect("generate failed");
+        assert_eq!(hotp.verify_strict(&pad, KEY, 100), Ok(true));
+    }
+}
+
+#[cfg(test)]
+mod tests_counter {
+    use crate::hotp::Hotp;
+
+    // RFC 4226 Appendix D, the ASCII secret "12345678901234567890"
+    const RFC_SECRET_HEX: &str = "3132333435363738393031323334353637383930";
+
+    #[test]
+    fn test_max_counter() {
+        let mut hotp = Hotp::new();
+        hotp.with_secret_hex(RFC_SECRET_HEX).expect("valid hex");
+        let pad = hotp.generate("", u64::MAX).expect("generate failed");
+        assert_eq!(pad, hotp.generate("", u64::MAX).expect("generate failed"));
+        assert_ne!(
+            pad,
+            hotp.generate("", u64::MAX - 1).expect("generate failed")
+        );
+
+        // The window stops at the end of the counter space rather than overflowing
+        hotp.with_window(5);
+        assert!(hotp.verify(&pad, "", u64::MAX - 1).expect("verify failed"));
+    }
+}
+
+#[cfg(test)]
+mod tests_algorithm {
+    use crate::hotp::Hotp;
+    use crate::Algorithm;
+
+    #[test]
+    fn test_sha256() {
+        // RFC 6238 Appendix B uses a 32 byte secret for SHA256, T = 1 is counter 1
+        let key = "12345678901234567890123456789012";
+        let mut hotp = Hotp::new();
+        hotp.with_algorithm(Algorithm::Sha256);
+        let pad = hotp.generate(key, 1).expect("generate failed");
+        assert_eq!(pad, "119246");
+        assert!(hotp.verify(&pad, key, 1).expect("verify failed"));
+
+        hotp.with_algorithm(Algorithm::Sha1);
+        assert!(!hotp.verify(&pad, key, 1).expect("verify failed"));
     }
 }
@@ -1,52 +1,306 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
 use hmac::{crypto_mac, Hmac, Mac, NewMac};
-use rand;
-use sha1::Sha1;
+#[cfg(feature = "std")]
+use rand::rngs::OsRng;
+#[cfg(feature = "std")]
+use rand::{CryptoRng, Rng, RngCore};
+use sha1::{Digest, Sha1};
 use sha2::{Sha256, Sha512};
-use thiserror::Error;
-use url::form_urlencoded::byte_serialize;
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
 
 type HmacSha1 = Hmac<Sha1>;
 type HmacSha256 = Hmac<Sha256>;
 type HmacSha512 = Hmac<Sha512>;
 
-mod hotp;
-mod totp;
+pub mod hotp;
+#[cfg(feature = "std")]
+pub mod migration;
+#[cfg(feature = "std")]
+pub mod otpauth;
+pub mod replay;
+mod secret;
+pub mod totp;
 
-pub use totp::Totp;
 pub use hotp::Hotp;
+#[cfg(feature = "std")]
+pub use migration::parse_migration_payload;
+#[cfg(feature = "std")]
+pub use otpauth::{parse_otpauth_url, OtpAuth, OtpAuthUrlBuilder};
+#[cfg(feature = "std")]
+pub use replay::MemoryReplayGuard;
+pub use replay::ReplayGuard;
+pub use totp::{Totp, TotpBuilder};
+
+use secret::Secret;
 
 /// GenerationError enumerates all possible errors returned by this library.
-#[derive(Error, Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum GenerationError {
-    #[error("Invalid Key Length")]
-    InvalidKeyLength(#[from] crypto_mac::InvalidKeyLength),
-    #[error("Failed to generate One-Time Password")]
+    InvalidKeyLength(crypto_mac::InvalidKeyLength),
     FailedToGenerateOTP(),
+    BufferTooSmall(usize),
+    FailedToGenerateQrCode(),
+    InvalidHexSecret(),
+    MissingTime(),
+    KeyTooShort { got: usize, min: usize },
+    TokenLengthMismatch { expected: usize, got: usize },
+    InvalidDigits(u32),
+    InvalidDigestLength(usize),
+    InvalidOtpAuthUrl(),
+    UnknownAlgorithm(String),
+    InvalidPeriod(),
+    EmptyCharset(),
+    InvalidMigrationPayload(),
+    InvalidBase32(),
+    MissingSecret(),
+    TimeBeforeEpoch(),
+    EmptyKey(),
+    CounterOverflow(),
+}
+
+impl fmt::Display for GenerationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenerationError::InvalidKeyLength(_) => write!(f, "Invalid Key Length"),
+            GenerationError::FailedToGenerateOTP() => {
+                write!(f, "Failed to generate One-Time Password")
+            }
+            GenerationError::BufferTooSmall(needed) => {
+                write!(f, "Output buffer too small, {} bytes needed", needed)
+            }
+            GenerationError::FailedToGenerateQrCode() => write!(f, "Failed to generate QR code"),
+            GenerationError::InvalidHexSecret() => write!(f, "Secret is not valid hex"),
+            GenerationError::MissingTime() => {
+                write!(f, "No time was set and there is no system clock")
+            }
+            GenerationError::KeyTooShort { got, min } => {
+                write!(f, "Key is {} bytes, at least {} are required", got, min)
+            }
+            GenerationError::TokenLengthMismatch { expected, got } => {
+                write!(f, "Token is {} characters, expected {}", got, expected)
+            }
+            GenerationError::InvalidDigits(digits) => write!(
+                f,
+                "{} digits requested, codes must be between 1 and {} digits",
+                digits, MAX_DIGITS
+            ),
+            GenerationError::InvalidDigestLength(len) => write!(
+                f,
+                "Digest is {} bytes, at least {} are required",
+                len, MIN_DIGEST_LENGTH
+            ),
+            GenerationError::InvalidOtpAuthUrl() => write!(f, "Not a valid otpauth URL"),
+            GenerationError::UnknownAlgorithm(name) => write!(f, "Unknown algorithm {}", name),
+            GenerationError::InvalidPeriod() => write!(f, "Period must be at least 1 second"),
+            GenerationError::EmptyCharset() => write!(f, "Charset must not be empty"),
+            GenerationError::InvalidMigrationPayload() => {
+                write!(f, "Not a valid authenticator export payload")
+            }
+            GenerationError::InvalidBase32() => write!(f, "Secret is not valid Base32"),
+            GenerationError::MissingSecret() => write!(f, "No secret has been set"),
+            GenerationError::TimeBeforeEpoch() => write!(f, "Time is before the epoch"),
+            GenerationError::EmptyKey() => write!(f, "Key must not be empty"),
+            GenerationError::CounterOverflow() => write!(f, "Counter would overflow"),
+        }
+    }
 }
 
+#[cfg(feature = "std")]
+impl std::error::Error for GenerationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GenerationError::InvalidKeyLength(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<crypto_mac::InvalidKeyLength> for GenerationError {
+    fn from(e: crypto_mac::InvalidKeyLength) -> Self {
+        GenerationError::InvalidKeyLength(e)
+    }
+}
+
+/// A common interface over `Hotp` and `Totp`, for code that works with either, e.g. through
+/// a `Box<dyn Otp>`.
+///
+/// Both are computed from a moving factor, which is the event counter for HOTP and the Unix
+/// time in seconds for TOTP. Verification applies the configured window either way.
+///
+/// # Examples
+///
+/// ```
+/// use lugnut::{ Hotp, Otp, Totp };
+/// let otps: Vec<Box<dyn Otp>> = vec![Box::new(Hotp::new()), Box::new(Totp::new())];
+/// for otp in &otps {
+///     let code = otp.generate("my super secret key", 59).unwrap();
+///     assert!(otp.verify(&code, "my super secret key", 59).unwrap());
+/// }
+/// ```
+pub trait Otp {
+    /// Generate the code for `key` at the moving factor `factor`.
+    fn generate(&self, key: &str, factor: u64) -> core::result::Result<String, GenerationError>;
+
+    /// Verify `token` against `key` at the moving factor `factor`.
+    fn verify(
+        &self,
+        token: &str,
+        key: &str,
+        factor: u64,
+    ) -> core::result::Result<bool, GenerationError>;
+}
+
+/// The minimum HMAC key length in bytes (128 bits) enforced by `Hotp` and `Totp`, per the
+/// recommendation in RFC 4226 section 4. It can be changed with `with_min_key_length`.
+pub const DEFAULT_MIN_KEY_LENGTH: usize = 16;
+
+/// The longest code that can be generated. Dynamic truncation produces a 31-bit integer, which
+/// has at most 10 decimal digits, so longer codes would only ever be zero padded.
+pub const MAX_DIGITS: u32 = 10;
+
+/// The shortest digest dynamic truncation accepts, the length of an HMAC-SHA-1 output.
+pub const MIN_DIGEST_LENGTH: usize = 20;
+
 enum HmacFunction<A, B, C> {
     Sha1(A),
     Sha256(B),
     Sha512(C),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Algorithm {
     Sha1,
     Sha256,
     Sha512,
 }
 
+impl Algorithm {
+    /// Reads the `algorithm` parameter of an otpauth URL, defaulting to SHA1 when it is absent
+    /// and otherwise parsing it ignoring case.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::Algorithm;
+    /// assert_eq!(Algorithm::from_otpauth(None), Ok(Algorithm::Sha1));
+    /// assert_eq!(Algorithm::from_otpauth(Some("sha256")), Ok(Algorithm::Sha256));
+    /// ```
+    pub fn from_otpauth(
+        algorithm: Option<&str>,
+    ) -> core::result::Result<Algorithm, GenerationError> {
+        algorithm.map_or(Ok(Algorithm::Sha1), str::parse)
+    }
+}
+
+/// Parses an algorithm name as used in otpauth URLs, e.g. `SHA256`, ignoring case.
+impl FromStr for Algorithm {
+    type Err = GenerationError;
+
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "SHA1" => Ok(Algorithm::Sha1),
+            "SHA256" => Ok(Algorithm::Sha256),
+            "SHA512" => Ok(Algorithm::Sha512),
+            _ => Err(GenerationError::UnknownAlgorithm(s.to_string())),
+        }
+    }
+}
+
+/// Formats the algorithm the way otpauth URLs name it, e.g. `SHA1`.
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Algorithm::Sha1 => write!(f, "SHA1"),
+            Algorithm::Sha256 => write!(f, "SHA256"),
+            Algorithm::Sha512 => write!(f, "SHA512"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
 static CHAR_SET: [char; 62] = [
     '1', '2', '3', '4', '5', '6', '7', '8', '9', '0', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I',
     'J', 'K', 'L', 'M', 'N', 'O', 'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z', 'a', 'b',
     'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's', 't', 'u',
     'v', 'w', 'x', 'y', 'z',
 ];
+#[cfg(feature = "std")]
 static SYMBOL_SET: [char; 22] = [
     '!', '@', '#', '$', '%', '^', '&', '*', '(', ')', '<', '>', '?', '/', '[', ']', '{', '}', ',',
     '.', ':', ';',
 ];
 
+/// The moving factor an OTP is computed from: the event counter for HOTP, or the number of
+/// time steps since the epoch for TOTP. Keeping it distinct from `u64` stops a Unix timestamp
+/// being passed where a counter is expected.
+///
+/// # Examples
+///
+/// ```
+/// use lugnut::{ digest, Algorithm, Counter };
+/// let hash = digest("My secret".to_string(), Counter::from(5000), Algorithm::Sha1);
+/// ```
+///
+/// A raw timestamp is not a counter:
+///
+/// ```compile_fail
+/// use lugnut::{ digest, Algorithm };
+/// let hash = digest("My secret".to_string(), 1_111_111_109u64, Algorithm::Sha1);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Counter(pub u64);
+
+impl Counter {
+    /// The TOTP counter for `unix_time`, the number of whole `step`s since `epoch`.
+    /// Returns `GenerationError::InvalidPeriod` for a `step` of 0 and
+    /// `GenerationError::TimeBeforeEpoch` for a `unix_time` earlier than `epoch`.
+    pub fn from_unix_time(
+        unix_time: u64,
+        epoch: u64,
+        step: u64,
+    ) -> core::result::Result<Counter, GenerationError> {
+        if step == 0 {
+            return Err(GenerationError::InvalidPeriod());
+        }
+        let elapsed = unix_time
+            .checked_sub(epoch)
+            .ok_or(GenerationError::TimeBeforeEpoch())?;
+        Ok(Counter(elapsed / step))
+    }
+
+    #[doc(hidden)]
+    fn saturating_sub(self, n: u64) -> Counter {
+        Counter(self.0.saturating_sub(n))
+    }
+
+    #[doc(hidden)]
+    fn saturating_add(self, n: u64) -> Counter {
+        Counter(self.0.saturating_add(n))
+    }
+}
+
+impl From<u64> for Counter {
+    fn from(counter: u64) -> Self {
+        Counter(counter)
+    }
+}
+
+impl From<Counter> for u64 {
+    fn from(counter: Counter) -> Self {
+        counter.0
+    }
+}
+
 /// Applys a specified keyed hashing function (hmac).
 ///
 /// # Arguments
@@ -58,41 +312,369 @@ static SYMBOL_SET: [char; 22] = [
 /// # Examples
 ///
 /// ```
-/// use lugnut::{ digest, Algorithm };
-/// let hash = digest("My secret".to_string(), 5000, Algorithm::Sha1);
-///
+/// use lugnut::{ digest, Algorithm, Counter };
+/// let hash = digest("My secret".to_string(), Counter(5000), Algorithm::Sha1);
+/// ```
 pub fn digest(
     secret: String,
-    counter: u128,
+    counter: Counter,
+    algorithm: Algorithm,
+) -> core::result::Result<Vec<u8>, GenerationError> {
+    digest_bytes(secret.as_bytes(), counter, algorithm)
+}
+
+/// Applies a specified keyed hashing function (hmac) over raw key bytes, for secrets that were
+/// decoded from another encoding such as Base32 or hex and need not be valid UTF-8.
+///
+/// # Arguments
+///
+/// * `key` - The bytes of the secret
+/// * `counter` - The counter to hash
+/// * `algorithm` - The preferred algorithm
+///
+/// # Examples
+///
+/// ```
+/// use lugnut::{ digest_bytes, Algorithm, Counter };
+/// let hash = digest_bytes(&[0xde, 0xad, 0xbe, 0xef], Counter(5000), Algorithm::Sha1);
+/// ```
+pub fn digest_bytes(
+    key: &[u8],
+    counter: Counter,
+    algorithm: Algorithm,
+) -> core::result::Result<Vec<u8>, GenerationError> {
+    DigestBuilder::new(key, algorithm).finalize_counter(counter)
+}
+
+/// Applies the keyed hashing function (hmac) with key material fed in chunks, for secrets that
+/// are streamed rather than held in memory at once.
+///
+/// As in HMAC itself, key material longer than the hash block size is hashed down, so the
+/// digest is the same as passing the whole secret to `digest`.
+///
+/// # Examples
+///
+/// ```
+/// use lugnut::{ digest, Algorithm, Counter, DigestBuilder };
+/// let mut builder = DigestBuilder::new(b"My ", Algorithm::Sha1);
+/// builder.update(b"secret");
+/// let hash = builder.finalize_counter(Counter(5000));
+/// assert_eq!(hash, digest("My secret".to_string(), Counter(5000), Algorithm::Sha1));
+/// ```
+pub struct DigestBuilder {
     algorithm: Algorithm,
-) -> std::result::Result<Vec<u8>, GenerationError> {
-    let mac = get_hmac(secret, algorithm)?;
-
-    // Convert the counter into a u8 array of base16 values
-    let mut buf = vec![0; 8];
-    let mut tmp = counter;
-    for i in 0..8 {
-        buf[7 - i] = (tmp & 0xff) as u8;
-        tmp = tmp >> 8;
-    }
-
-    // Unwrap enum and apply the hmac alg
-    Ok(match mac {
-        HmacFunction::Sha1(mut _mac) => {
-            _mac.update(&buf);
-            _mac.finalize().into_bytes().to_vec()
+    key: KeyMaterial,
+}
+
+enum KeyMaterial {
+    Buffered(Secret),
+    Hashed(Box<HmacFunction<Sha1, Sha256, Sha512>>),
+}
+
+impl DigestBuilder {
+    /// Start a digest with the first chunk of key material.
+    ///
+    /// # Arguments
+    ///
+    /// * `secret` - The first chunk of the secret, may be empty
+    /// * `algorithm` - The preferred algorithm
+    pub fn new(secret: &[u8], algorithm: Algorithm) -> DigestBuilder {
+        let mut builder = DigestBuilder {
+            algorithm,
+            key: KeyMaterial::Buffered(Secret::with_capacity(block_size(algorithm))),
+        };
+        builder.update(secret);
+        builder
+    }
+
+    /// Append the next chunk of key material.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The next chunk of the secret
+    pub fn update(&mut self, data: &[u8]) -> &mut DigestBuilder {
+        match &mut self.key {
+            KeyMaterial::Buffered(key) if key.len() + data.len() <= block_size(self.algorithm) => {
+                key.extend_from_slice(data);
+            }
+            KeyMaterial::Buffered(key) => {
+                // Too long to be used as the HMAC key directly, switch to hashing it
+                let mut hasher = match self.algorithm {
+                    Algorithm::Sha1 => HmacFunction::Sha1(Sha1::new()),
+                    Algorithm::Sha256 => HmacFunction::Sha256(Sha256::new()),
+                    Algorithm::Sha512 => HmacFunction::Sha512(Sha512::new()),
+                };
+                hasher.update(key.as_bytes());
+                hasher.update(data);
+                self.key = KeyMaterial::Hashed(Box::new(hasher));
+            }
+            KeyMaterial::Hashed(hasher) => hasher.update(data),
         }
-        HmacFunction::Sha256(mut _mac) => {
-            _mac.update(&buf);
-            _mac.finalize().into_bytes().to_vec()
+        self
+    }
+
+    /// Apply the keyed hashing function to `counter`, keyed with all the material fed so far.
+    ///
+    /// # Arguments
+    ///
+    /// * `counter` - The counter to hash
+    pub fn finalize_counter(
+        self,
+        counter: Counter,
+    ) -> core::result::Result<Vec<u8>, GenerationError> {
+        let key = match self.key {
+            KeyMaterial::Buffered(key) => key,
+            KeyMaterial::Hashed(hasher) => Secret::from(hasher.finalize()),
+        };
+        let mac = get_hmac(key.as_bytes(), self.algorithm)?;
+
+        #[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+        let mut buf = counter_to_bytes(counter);
+
+        // Unwrap enum and apply the hmac alg
+        let hash = match mac {
+            HmacFunction::Sha1(mac) => mac_counter(mac, &buf),
+            HmacFunction::Sha256(mac) => mac_counter(mac, &buf),
+            HmacFunction::Sha512(mac) => mac_counter(mac, &buf),
+        };
+
+        #[cfg(feature = "zeroize")]
+        buf.zeroize();
+
+        Ok(hash)
+    }
+}
+
+impl HmacFunction<Sha1, Sha256, Sha512> {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            HmacFunction::Sha1(hasher) => Digest::update(hasher, data),
+            HmacFunction::Sha256(hasher) => Digest::update(hasher, data),
+            HmacFunction::Sha512(hasher) => Digest::update(hasher, data),
         }
-        HmacFunction::Sha512(mut _mac) => {
-            _mac.update(&buf);
-            _mac.finalize().into_bytes().to_vec()
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        match self {
+            HmacFunction::Sha1(hasher) => hasher.finalize().to_vec(),
+            HmacFunction::Sha256(hasher) => hasher.finalize().to_vec(),
+            HmacFunction::Sha512(hasher) => hasher.finalize().to_vec(),
         }
-    })
+    }
+}
+
+/// The block size of the hash, the longest key HMAC uses without hashing it first.
+fn block_size(algorithm: Algorithm) -> usize {
+    match algorithm {
+        Algorithm::Sha1 | Algorithm::Sha256 => 64,
+        Algorithm::Sha512 => 128,
+    }
 }
 
+fn mac_counter<M: Mac>(mut mac: M, buf: &[u8]) -> Vec<u8> {
+    mac.update(buf);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Convert the counter into the 8 byte big-endian moving factor RFC 4226 feeds to the HMAC.
+pub(crate) fn counter_to_bytes(counter: Counter) -> [u8; 8] {
+    counter.0.to_be_bytes()
+}
+
+#[cfg(feature = "std")]
+/// Generates `length` random bytes of key material, for callers that encode the secret
+/// themselves rather than using an ASCII representation
+///
+/// # Examples
+///
+/// ```
+/// use lugnut::{ generate_secret_bytes };
+/// let secret_key = generate_secret_bytes(20);
+/// ```
+pub fn generate_secret_bytes(length: u32) -> Vec<u8> {
+    generate_secret_bytes_with_rng(&mut OsRng, length)
+}
+
+#[cfg(feature = "std")]
+/// Generates `length` random bytes of key material from the given cryptographically secure RNG
+///
+/// # Examples
+///
+/// ```
+/// use lugnut::{ generate_secret_bytes_with_rng };
+/// let secret_key = generate_secret_bytes_with_rng(&mut rand::rngs::OsRng, 20);
+/// ```
+pub fn generate_secret_bytes_with_rng<R: RngCore + CryptoRng>(rng: &mut R, length: u32) -> Vec<u8> {
+    let mut bytes = vec![0; length as usize];
+    rng.fill_bytes(&mut bytes);
+    bytes
+}
+
+#[cfg(feature = "std")]
+/// Generates a secret key in ASCII representation from the given cryptographically secure RNG,
+/// so the output can be made deterministic by seeding it
+///
+/// # Examples
+///
+/// ```
+/// use lugnut::{ generate_secret_with_rng };
+/// let secret_key = generate_secret_with_rng(&mut rand::rngs::OsRng, 32, true);
+/// ```
+pub fn generate_secret_with_rng<R: RngCore + CryptoRng>(
+    rng: &mut R,
+    length: u32,
+    symbols: bool,
+) -> String {
+    if symbols {
+        let alphabet: Vec<char> = CHAR_SET.iter().chain(SYMBOL_SET.iter()).copied().collect();
+        sample_alphabet(rng, length, &alphabet)
+    } else {
+        sample_alphabet(rng, length, &CHAR_SET)
+    }
+}
+
+#[cfg(feature = "std")]
+/// Generates a secret key of `length` characters drawn uniformly from `charset`, e.g. to leave
+/// out ambiguous characters like `0`/`O` and `1`/`l`
+///
+/// # Arguments
+///
+/// * `length` - The number of characters to generate
+/// * `charset` - The characters to choose from, which must not be empty
+///
+/// # Examples
+///
+/// ```
+/// use lugnut::{ generate_secret_with_charset };
+/// let secret_key = generate_secret_with_charset(32, &['A', 'B', 'C', 'D', '2', '3', '4', '5']);
+/// ```
+pub fn generate_secret_with_charset(
+    length: u32,
+    charset: &[char],
+) -> core::result::Result<String, GenerationError> {
+    if charset.is_empty() {
+        return Err(GenerationError::EmptyCharset());
+    }
+    Ok(sample_alphabet(&mut OsRng, length, charset))
+}
+
+#[cfg(feature = "std")]
+#[doc(hidden)]
+fn sample_alphabet<R: RngCore + CryptoRng>(rng: &mut R, length: u32, alphabet: &[char]) -> String {
+    // gen_range rejects samples from the biased tail of the range, so every character is
+    // equally likely regardless of the alphabet size
+    (0..length)
+        .map(|_| alphabet[rng.gen_range(0..alphabet.len())])
+        .collect()
+}
+
+#[cfg(feature = "std")]
+/// Returns the entropy in bits of a secret of `length` characters drawn uniformly from a
+/// charset of `charset_size` characters, e.g. to check a configuration meets a 128 bit policy
+///
+/// # Examples
+///
+/// ```
+/// use lugnut::{ secret_entropy_bits };
+/// // A 32 character Base32 secret
+/// assert_eq!(secret_entropy_bits(32, 32), 160.0);
+/// ```
+pub fn secret_entropy_bits(length: u32, charset_size: usize) -> f64 {
+    f64::from(length) * (charset_size as f64).log2()
+}
+
+#[cfg(feature = "std")]
+/// Estimates the entropy in bits of existing key material, e.g. a secret supplied by a user or
+/// another system, from the distribution of its bytes. Both the bytes and the differences
+/// between neighbouring bytes are measured and the lower estimate is used, so a sequence like
+/// `1, 2, 3, ...` isn't mistaken for random data. This is only a heuristic for rejecting
+/// obviously bad keys, it can't prove a secret was randomly generated.
+///
+/// # Arguments
+///
+/// * `secret` - The bytes of the secret
+///
+/// # Examples
+///
+/// ```
+/// use lugnut::{ estimate_secret_entropy };
+/// assert_eq!(estimate_secret_entropy(&[0u8; 20]), 0.0);
+/// ```
+pub fn estimate_secret_entropy(secret: &[u8]) -> f64 {
+    let deltas: Vec<u8> = secret
+        .windows(2)
+        .map(|pair| pair[1].wrapping_sub(pair[0]))
+        .collect();
+    let bits_per_byte = shannon_entropy(secret).min(shannon_entropy(&deltas));
+    bits_per_byte * secret.len() as f64
+}
+
+#[cfg(feature = "std")]
+/// Flags key material that is empty, a single repeated byte or a sequence with a constant step
+/// between bytes, such as `0, 1, 2, ...`. See `estimate_secret_entropy` for a finer measure.
+///
+/// # Arguments
+///
+/// * `secret` - The bytes of the secret
+///
+/// # Examples
+///
+/// ```
+/// use lugnut::{ generate_secret_bytes, is_weak_secret };
+/// assert!(is_weak_secret(b"aaaaaaaaaaaaaaaaaaaa"));
+/// assert!(!is_weak_secret(&generate_secret_bytes(20)));
+/// ```
+pub fn is_weak_secret(secret: &[u8]) -> bool {
+    let mut deltas = secret.windows(2).map(|pair| pair[1].wrapping_sub(pair[0]));
+    match deltas.next() {
+        Some(first) => deltas.all(|delta| delta == first),
+        None => true,
+    }
+}
+
+#[cfg(feature = "std")]
+#[doc(hidden)]
+fn shannon_entropy(bytes: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for byte in bytes {
+        counts[*byte as usize] += 1;
+    }
+    let total = bytes.len() as f64;
+    counts
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[cfg(feature = "std")]
+/// Decodes a user supplied Base32 secret, e.g. before storing it, so a secret that can't be used
+/// is rejected up front rather than failing later at generation time. Lowercase letters and
+/// trailing `=` padding are accepted.
+///
+/// # Arguments
+///
+/// * `secret` - The Base32 encoded secret
+///
+/// # Examples
+///
+/// ```
+/// use lugnut::{ validate_base32_secret };
+/// assert_eq!(validate_base32_secret("ONSWG4TFOQ======"), Ok(b"secret".to_vec()));
+/// ```
+pub fn validate_base32_secret(secret: &str) -> core::result::Result<Vec<u8>, GenerationError> {
+    let secret = secret.trim_end_matches('=').to_ascii_uppercase();
+    if secret.is_empty() {
+        return Err(GenerationError::InvalidBase32());
+    }
+    base32::decode(base32::Alphabet::RFC4648 { padding: false }, &secret)
+        .ok_or(GenerationError::InvalidBase32())
+}
+
+#[cfg(feature = "std")]
 /// Default layer to generate a secret key in ASCII representations
 ///
 /// # Examples
@@ -105,6 +687,7 @@ pub fn generate_secret() -> String {
     generate_secret_default(None, None)
 }
 
+#[cfg(feature = "std")]
 /// Length defining layer to generate a secret key in ASCII representation
 ///
 /// # Examples
@@ -117,6 +700,7 @@ pub fn generate_sized_secret(length: u32) -> String {
     generate_secret_default(Some(length), None)
 }
 
+#[cfg(feature = "std")]
 /// Symbol defining layer to generate a secret key in ASCII representation
 ///
 /// # Examples
@@ -129,6 +713,7 @@ pub fn generate_secret_without_symbols() -> String {
     generate_secret_default(None, Some(false))
 }
 
+#[cfg(feature = "std")]
 /// Symbol and length defining layer to generate a secret key in ASCII representation
 ///
 /// # Examples
@@ -141,151 +726,716 @@ pub fn generate_sized_secret_without_symbols(length: u32) -> String {
     generate_secret_default(Some(length), Some(true))
 }
 
-pub fn get_otp_auth_url() {}
+#[cfg(feature = "std")]
+/// Generates a set of random numeric backup (recovery) codes
+///
+/// These are independent of any HOTP/TOTP secret, so they should be hashed before being stored.
+///
+/// # Arguments
+///
+/// * `count` - The number of codes to generate
+/// * `length` - The number of digits in each code
+///
+/// # Examples
+///
+/// ```
+/// use lugnut::{ generate_backup_codes };
+/// let codes = generate_backup_codes(10, 8);
+/// ```
+pub fn generate_backup_codes(count: usize, length: usize) -> Vec<String> {
+    generate_grouped_backup_codes(count, length, 0)
+}
 
-/// This section works to fill up the unsigned 32 bit number by:
+#[cfg(feature = "std")]
+/// Generates a set of random numeric backup codes, inserting a hyphen every `group` digits
+/// (e.g. `1234-5678`). A `group` of 0 leaves the codes ungrouped.
+///
+/// # Examples
+///
+/// ```
+/// use lugnut::{ generate_grouped_backup_codes };
+/// let codes = generate_grouped_backup_codes(10, 8, 4);
+/// ```
+pub fn generate_grouped_backup_codes(count: usize, length: usize, group: usize) -> Vec<String> {
+    let mut rng = OsRng;
+    (0..count)
+        .map(|_| {
+            let mut code = String::with_capacity(length + length / group.max(1));
+            for i in 0..length {
+                if group > 0 && i > 0 && i % group == 0 {
+                    code.push('-');
+                }
+                code.push(char::from(b'0' + rng.gen_range(0..10u8)));
+            }
+            code
+        })
+        .collect()
+}
+
+/// The PBKDF2-HMAC-SHA256 iteration count `hash_backup_code` uses, following the OWASP
+/// password storage recommendation. Backup codes are short, so a slow hash is what keeps a
+/// leaked hash from being brute forced.
+pub const BACKUP_CODE_HASH_ITERATIONS: u32 = 600_000;
+
+#[cfg(feature = "std")]
+/// Hashes a backup code with a random salt for storage, so a leaked database doesn't reveal
+/// usable codes. Spaces and hyphens are ignored, so grouped codes hash the same as ungrouped ones.
+///
+/// The result is a self describing string, `$pbkdf2-sha256$i=<iterations>$<salt>$<hash>` with
+/// unpadded Base64 salt and hash, to be checked with `verify_backup_code`.
+///
+/// # Arguments
+///
+/// * `code` - The backup code handed to the user
+///
+/// # Examples
+///
+/// ```no_run
+/// use lugnut::{ generate_backup_codes, hash_backup_code };
+/// let hashes: Vec<String> = generate_backup_codes(10, 8)
+///     .iter()
+///     .map(|code| hash_backup_code(code))
+///     .collect();
+/// ```
+pub fn hash_backup_code(code: &str) -> String {
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    hash_backup_code_with(code, &salt, BACKUP_CODE_HASH_ITERATIONS)
+}
+
+#[cfg(feature = "std")]
+/// Checks a code submitted by the user against a hash from `hash_backup_code`. Malformed hashes
+/// never verify, nor do hashes claiming more than 4 times `BACKUP_CODE_HASH_ITERATIONS`, so a
+/// tampered row can't make a single check run for minutes.
+///
+/// # Arguments
+///
+/// * `code` - The code submitted by the user
+/// * `hash` - The stored hash
+///
+/// # Examples
+///
+/// ```no_run
+/// use lugnut::{ hash_backup_code, verify_backup_code };
+/// let hash = hash_backup_code("1234-5678");
+/// assert!(verify_backup_code("12345678", &hash));
+/// ```
+pub fn verify_backup_code(code: &str, hash: &str) -> bool {
+    let mut parts = hash.split('$');
+    let (iterations, salt, expected) = match (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    ) {
+        (Some(""), Some("pbkdf2-sha256"), Some(iterations), Some(salt), Some(expected), None) => {
+            (iterations, salt, expected)
+        }
+        _ => return false,
+    };
+    let iterations = match iterations.strip_prefix("i=").map(str::parse::<u32>) {
+        Some(Ok(iterations)) if iterations > 0 && iterations <= 4 * BACKUP_CODE_HASH_ITERATIONS => {
+            iterations
+        }
+        _ => return false,
+    };
+    let (salt, expected) = match (
+        base64::decode_config(salt, base64::STANDARD_NO_PAD),
+        base64::decode_config(expected, base64::STANDARD_NO_PAD),
+    ) {
+        (Ok(salt), Ok(expected)) => (salt, expected),
+        _ => return false,
+    };
+    let derived = pbkdf2_sha256(normalize_token(code).as_bytes(), &salt, iterations);
+    // Compare every byte so the time taken doesn't reveal how much of the hash matched
+    derived.len() == expected.len()
+        && derived
+            .iter()
+            .zip(expected.iter())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(feature = "std")]
+#[doc(hidden)]
+fn hash_backup_code_with(code: &str, salt: &[u8], iterations: u32) -> String {
+    let hash = pbkdf2_sha256(normalize_token(code).as_bytes(), salt, iterations);
+    format!(
+        "$pbkdf2-sha256$i={}${}${}",
+        iterations,
+        base64::encode_config(salt, base64::STANDARD_NO_PAD),
+        base64::encode_config(hash, base64::STANDARD_NO_PAD),
+    )
+}
+
+#[cfg(feature = "std")]
+/// PBKDF2 (RFC 8018) with HMAC-SHA256, deriving a single 32 byte block.
+#[doc(hidden)]
+fn pbkdf2_sha256(password: &[u8], salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut derived = [0u8; 32];
+    pbkdf2::pbkdf2::<HmacSha256>(password, salt, iterations, &mut derived);
+    derived
+}
+
+/// Formats a code for display by inserting `sep` every `group` characters, e.g. `123 456`.
+/// A trailing group shorter than `group` is kept as is, and a `group` of 0 leaves the code
+/// unchanged.
+///
+/// # Arguments
+///
+/// * `code` - The code to format
+/// * `group` - The number of characters per group
+/// * `sep` - The separator placed between groups
+///
+/// # Examples
+///
+/// ```
+/// use lugnut::{ format_code };
+/// assert_eq!(format_code("123456", 3, " "), "123 456");
+/// ```
+pub fn format_code(code: &str, group: usize, sep: &str) -> String {
+    if group == 0 {
+        return code.to_string();
+    }
+    let mut formatted = String::with_capacity(code.len() + code.len() / group * sep.len());
+    for (i, c) in code.chars().enumerate() {
+        if i > 0 && i % group == 0 {
+            formatted.push_str(sep);
+        }
+        formatted.push(c);
+    }
+    formatted
+}
+
+/// Allocating wrapper around [`generate_otp_into`] that returns the token as a `String`.
+#[doc(hidden)]
+fn generate_otp(
+    digits: u32,
+    digest_hash: Vec<u8>,
+) -> core::result::Result<String, GenerationError> {
+    check_digits(digits)?;
+    let mut buf = vec![0; digits as usize];
+    generate_otp_into(digits, &digest_hash, &mut buf).map(|code| code.to_string())
+}
+
+/// Performs the RFC 4226 dynamic truncation of a digest, returning the 31-bit value the code
+/// is taken from. Useful for diagnosing interop failures.
+///
+/// This works to fill up the unsigned 32 bit number by:
 /// 1.  Taking the 8 bits at the offset from the digest, AND'ing with 0x7f so that we can ignore the sign bit
-/// and then bit shifting 24 to the left to fill the most significant bits.
+///     and then bit shifting 24 to the left to fill the most significant bits.
 /// 2.  Taking the next 8 bits from the digest at (offset + 1), AND'ing with 0xff to get the set bits, shifting 16 to fill
-/// the next 8 significant bits.
+///     the next 8 significant bits.
 /// 3.  Same as (2.) but taking the bits from (offset + 2)
 /// 4.  Same as (2.) but taking the bits from (offset + 3)
 /// 5.  OR'ing each of these u32 so that we collapse all of the set bits into one u32
+///
+/// # Arguments
+///
+/// * `digest_hash` - The HMAC output, at least `MIN_DIGEST_LENGTH` bytes
+///
+/// # Examples
+///
+/// ```
+/// use lugnut::{ digest, truncate, Algorithm, Counter };
+/// let hash = digest("my super secret key".to_string(), Counter(0), Algorithm::Sha1).unwrap();
+/// let value = truncate(&hash).unwrap();
+/// ```
+pub fn truncate(digest_hash: &[u8]) -> core::result::Result<u32, GenerationError> {
+    // Dynamic truncation reads 4 bytes from an offset of up to 15, so anything shorter than
+    // a SHA-1 digest could index out of range
+    if digest_hash.len() < MIN_DIGEST_LENGTH {
+        return Err(GenerationError::InvalidDigestLength(digest_hash.len()));
+    }
+
+    let offset = (digest_hash[digest_hash.len() - 1] & 0xf) as usize;
+    let no_offset = u32::from(digest_hash[offset] & 0x7f) << 24;
+    let one_offset = u32::from(digest_hash[offset + 1]) << 16;
+    let two_offset = u32::from(digest_hash[offset + 2]) << 8;
+    let three_offset = u32::from(digest_hash[offset + 3]);
+    Ok(no_offset | one_offset | two_offset | three_offset)
+}
+
+/// Writes the zero-padded token as ASCII digits into the front of `buf`, so this works
+/// without an allocator.
 #[doc(hidden)]
-fn generate_otp(
+fn generate_otp_into<'b>(
     digits: u32,
-    digest_hash: Vec<u8>,
-) -> std::result::Result<String, GenerationError> {
-    let offset = if let Some(o) = digest_hash.last() {
-        o & 0xf
-    } else {
-        0
-    };
-
-    let no_offset = if let Some(o) = digest_hash.get(offset as usize) {
-        u32::from(o.clone() & 0x7f) << 24
-    } else {
-        0
-    };
-    let one_offset = if let Some(o) = digest_hash.get((offset + 1) as usize) {
-        u32::from(o.clone() & 0xff) << 16
-    } else {
-        0
-    };
-    let two_offset = if let Some(o) = digest_hash.get((offset + 2) as usize) {
-        u32::from(o.clone() & 0xff) << 8
-    } else {
-        0
-    };
-    let three_offset = if let Some(o) = digest_hash.get((offset + 3) as usize) {
-        u32::from(o.clone() & 0xff)
-    } else {
-        0
-    };
-    let code = no_offset | one_offset | two_offset | three_offset;
+    digest_hash: &[u8],
+    buf: &'b mut [u8],
+) -> core::result::Result<&'b str, GenerationError> {
+    check_digits(digits)?;
+    let code = truncate(digest_hash)?;
 
     if code == 0 {
         // This is very unlikely to happen, but as a precaution we will return an Err
-        Err(GenerationError::FailedToGenerateOTP())
-    } else {
-        let padded_string = format!("{:0>width$}", code.to_string(), width = digits as usize);
-        Ok(
-            (&padded_string[(padded_string.len() - digits as usize)..padded_string.len()])
-                .to_string(),
-        )
+        return Err(GenerationError::FailedToGenerateOTP());
+    }
+
+    let digits = digits as usize;
+    if buf.len() < digits {
+        return Err(GenerationError::BufferTooSmall(digits));
     }
+
+    // Fill from the least significant digit, which both zero pads and keeps only the last
+    // `digits` digits of the code
+    let mut remaining = code;
+    for slot in buf[..digits].iter_mut().rev() {
+        *slot = b'0' + (remaining % 10) as u8;
+        remaining /= 10;
+    }
+    core::str::from_utf8(&buf[..digits]).map_err(|_| GenerationError::FailedToGenerateOTP())
 }
 
 #[doc(hidden)]
 fn verify_delta(
-    token: String,
-    counter: u128,
+    token: &str,
+    start: Counter,
+    end: Counter,
     digits: u32,
-    window: u64,
-    digest_hash: Vec<u8>,
-) -> std::result::Result<bool, GenerationError> {
+    hash: impl Fn(Counter) -> core::result::Result<Vec<u8>, GenerationError>,
+) -> core::result::Result<bool, GenerationError> {
+    find_counter(token, start, end, digits, hash).map(|counter| counter.is_some())
+}
+
+/// Returns the first counter in `start..=end` whose code matches `token`.
+#[doc(hidden)]
+fn find_counter(
+    token: &str,
+    start: Counter,
+    end: Counter,
+    digits: u32,
+    hash: impl Fn(Counter) -> core::result::Result<Vec<u8>, GenerationError>,
+) -> core::result::Result<Option<Counter>, GenerationError> {
+    check_digits(digits)?;
+    let token = normalize_token(token);
     if token.len() as u32 != digits {
-        return Ok(false);
+        return Ok(None);
     }
 
-    for _ in counter..=counter + window as u128 {
-        let test_otp = generate_otp(digits, digest_hash.clone())?;
+    for counter in (start.0..=end.0).map(Counter) {
+        let test_otp = generate_otp(digits, hash(counter)?)?;
         if test_otp == token {
-            return Ok(true);
+            return Ok(Some(counter));
         }
     }
 
-    // Default false
-    Ok(false)
+    // Default no match
+    Ok(None)
 }
 
+/// Returns the signed offset from `counter` of the counter in `counter - back ..= counter + forward`
+/// whose code matches `token`, positive when the client is ahead.
+#[doc(hidden)]
+fn find_delta(
+    token: &str,
+    counter: Counter,
+    back: u64,
+    forward: u64,
+    digits: u32,
+    hash: impl Fn(Counter) -> core::result::Result<Vec<u8>, GenerationError>,
+) -> core::result::Result<Option<i64>, GenerationError> {
+    let matched = find_counter(
+        token,
+        counter.saturating_sub(back),
+        counter.saturating_add(forward),
+        digits,
+        hash,
+    )?;
+    Ok(matched.map(|c| c.0.wrapping_sub(counter.0) as i64))
+}
+
+#[cfg(feature = "std")]
 #[doc(hidden)]
 fn generate_secret_default(length: Option<u32>, symbols: Option<bool>) -> String {
-    let defined_symbols = if let Some(s) = symbols { s } else { true };
-    let defined_length = if let Some(l) = length { l } else { 32 };
+    let defined_symbols = symbols.unwrap_or(true);
+    let defined_length = length.unwrap_or(32);
     generate_secret_ascii(defined_length, defined_symbols)
 }
 
+#[doc(hidden)]
+fn check_key_length(key: &[u8], min: usize) -> core::result::Result<(), GenerationError> {
+    if key.is_empty() {
+        Err(GenerationError::EmptyKey())
+    } else if key.len() < min {
+        Err(GenerationError::KeyTooShort {
+            got: key.len(),
+            min,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+#[doc(hidden)]
+fn check_digits(digits: u32) -> core::result::Result<(), GenerationError> {
+    if digits == 0 || digits > MAX_DIGITS {
+        Err(GenerationError::InvalidDigits(digits))
+    } else {
+        Ok(())
+    }
+}
+
+/// Strips the spaces and hyphens users type or paste between digit groups, e.g. `123 456`.
+/// Neither can appear in a code, so nothing meaningful is removed.
+#[doc(hidden)]
+fn normalize_token(token: &str) -> String {
+    token.chars().filter(|c| *c != ' ' && *c != '-').collect()
+}
+
+/// Zero pads a code that was stored as an integer, e.g. `26920` for `026920`, back to `digits`.
+#[doc(hidden)]
+fn numeric_token(code: u32, digits: u32) -> String {
+    alloc::format!("{:0width$}", code, width = digits as usize)
+}
+
+#[doc(hidden)]
+fn check_token_length(token: &str, digits: u32) -> core::result::Result<(), GenerationError> {
+    let token = normalize_token(token);
+    if token.len() != digits as usize {
+        Err(GenerationError::TokenLengthMismatch {
+            expected: digits as usize,
+            got: token.len(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
 #[doc(hidden)]
 fn get_hmac(
-    secret: String,
+    secret: &[u8],
     algorithm: Algorithm,
-) -> std::result::Result<HmacFunction<HmacSha1, HmacSha256, HmacSha512>, GenerationError> {
+) -> core::result::Result<HmacFunction<HmacSha1, HmacSha256, HmacSha512>, GenerationError> {
+    // HMAC accepts an empty key, but it is almost certainly a mistake by the caller
+    if secret.is_empty() {
+        return Err(GenerationError::EmptyKey());
+    }
     Ok(match algorithm {
-        Algorithm::Sha1 => HmacFunction::Sha1(HmacSha1::new_varkey(secret.as_bytes())?),
-        Algorithm::Sha256 => HmacFunction::Sha256(HmacSha256::new_varkey(secret.as_bytes())?),
-        Algorithm::Sha512 => HmacFunction::Sha512(HmacSha512::new_varkey(secret.as_bytes())?),
+        Algorithm::Sha1 => HmacFunction::Sha1(HmacSha1::new_varkey(secret)?),
+        Algorithm::Sha256 => HmacFunction::Sha256(HmacSha256::new_varkey(secret)?),
+        Algorithm::Sha512 => HmacFunction::Sha512(HmacSha512::new_varkey(secret)?),
     })
 }
 
+#[cfg(feature = "std")]
 #[doc(hidden)]
 fn generate_secret_ascii(length: u32, symbols: bool) -> String {
-    let byte_array: Vec<u8> = (0..length).map(|_| rand::random::<u8>()).collect();
-
-    let mut secret: String = String::from("");
-    for (_, value) in byte_array.iter().enumerate() {
-        // Need to decide to grab from the symbol/char set if configuration wants to add symbols to secret
-        if symbols {
-            secret.push(match value % 2 {
-                0 => CHAR_SET[((usize::from(value / 1)) * (CHAR_SET.len() - 1)) / 255],
-                1 => SYMBOL_SET[((usize::from(value / 1)) * (SYMBOL_SET.len() - 1)) / 255],
-                _ => unreachable!("Error: Reached the unreachable match arm of `u8` modulo 2"),
-            })
-        } else {
-            secret.push(CHAR_SET[((usize::from(value / 1)) * (CHAR_SET.len() - 1)) / 255])
-        }
-    }
-    secret
+    generate_secret_with_rng(&mut OsRng, length, symbols)
 }
 
-#[doc(hidden)]
-fn encode_uri_component(string: String) -> String {
-    byte_serialize(string.as_bytes()).collect()
+#[cfg(test)]
+mod error_tests {
+    use crate::{digest, digest_bytes, generate_otp, Algorithm, Counter, GenerationError};
+    use alloc::string::String;
+    use alloc::vec;
+
+    #[test]
+    fn test_error_eq() {
+        let err = generate_otp(6, vec![0; 20]).unwrap_err();
+        assert_eq!(err, GenerationError::FailedToGenerateOTP());
+        assert_eq!(err.clone(), err);
+        assert_ne!(err, GenerationError::BufferTooSmall(6));
+    }
+
+    #[test]
+    fn test_empty_key() {
+        assert_eq!(
+            digest(String::new(), Counter(0), Algorithm::Sha1),
+            Err(GenerationError::EmptyKey())
+        );
+        assert_eq!(
+            digest_bytes(&[], Counter(0), Algorithm::Sha256),
+            Err(GenerationError::EmptyKey())
+        );
+    }
 }
 
-#[doc(hidden)]
-fn generate_otpauth_url() {}
+#[cfg(all(test, not(feature = "std")))]
+mod no_std_tests {
+    use crate::{GenerationError, Hotp, Totp};
 
-#[cfg(test)]
+    const RFC_SECRET_HEX: &str = "3132333435363738393031323334353637383930";
+
+    #[test]
+    fn test_hotp_without_std() {
+        let mut hotp = Hotp::new();
+        hotp.with_secret_hex(RFC_SECRET_HEX).expect("valid hex");
+        let mut buf = [0u8; 6];
+        assert_eq!(hotp.generate_into("", 0, &mut buf), Ok("755224"));
+    }
+
+    #[test]
+    fn test_totp_without_std() {
+        let mut totp = Totp::new();
+        totp.with_secret_hex(RFC_SECRET_HEX).expect("valid hex");
+        assert_eq!(totp.generate(""), Err(GenerationError::MissingTime()));
+
+        totp.with_time(59);
+        let code = totp.generate("").expect("generate failed");
+        assert_eq!(code, "287082");
+        assert_eq!(totp.verify(&code, ""), Ok(true));
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
 mod digest_tests {
-    use crate::digest;
     use crate::Algorithm::Sha1;
+    use crate::{digest, digest_bytes, Algorithm, Counter, DigestBuilder};
 
     #[test]
     fn it_works() {
-        let test = digest("My secret".to_string(), 5000, Sha1);
+        let test = digest("My secret".to_string(), Counter(5000), Sha1);
         match test {
             Ok(result) => println!("Testing {:02x?}", result),
             Err(_) => panic!("There was an error in the test"),
         }
     }
+
+    #[test]
+    fn test_digest_non_utf8_key() {
+        // Invalid as UTF-8, so it couldn't be passed to `digest`
+        let key = [0xff, 0xfe, 0x00, 0x80, 0xc3, 0x28];
+        assert_eq!(
+            digest_bytes(&key, Counter(5000), Sha1),
+            Ok(hex::decode("c4fb53eb9f61a9808555e0afab46f08e3db7f249").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_streamed_key_matches_one_shot() {
+        // Long enough to be hashed down for every algorithm
+        let secret = "SuperDuperSecretKey".repeat(10);
+        for algorithm in [Algorithm::Sha1, Algorithm::Sha256, Algorithm::Sha512] {
+            for chunk_size in [1, 7, 64, secret.len()] {
+                let mut builder = DigestBuilder::new(&[], algorithm);
+                for chunk in secret.as_bytes().chunks(chunk_size) {
+                    builder.update(chunk);
+                }
+                assert_eq!(
+                    builder.finalize_counter(Counter(5000)),
+                    digest(secret.clone(), Counter(5000), algorithm)
+                );
+            }
+
+            let mut builder = DigestBuilder::new(b"My ", algorithm);
+            builder.update(b"secret");
+            assert_eq!(
+                builder.finalize_counter(Counter(5000)),
+                digest("My secret".to_string(), Counter(5000), algorithm)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod truncate_tests {
+    use crate::{truncate, GenerationError};
+
+    #[test]
+    fn test_truncate_rfc4226_intermediate_values() {
+        // RFC 4226 Appendix D, HMAC-SHA-1 of the test secret at counters 0 and 1
+        let hash = hex::decode("cc93cf18508d94934c64b65d8ba7667fb7cde4b0").unwrap();
+        assert_eq!(truncate(&hash), Ok(1284755224));
+        let hash = hex::decode("75a48a19d4cbe100644e8ac1397eea747a2d33ab").unwrap();
+        assert_eq!(truncate(&hash), Ok(1094287082));
+    }
+
+    #[test]
+    fn test_truncate_short_digest() {
+        assert_eq!(
+            truncate(&[1, 2, 3, 4]),
+            Err(GenerationError::InvalidDigestLength(4))
+        );
+    }
+}
+
+#[cfg(test)]
+mod algorithm_tests {
+    use crate::{Algorithm, GenerationError};
+
+    #[test]
+    fn test_from_otpauth() {
+        assert_eq!(Algorithm::from_otpauth(None), Ok(Algorithm::Sha1));
+        assert_eq!(
+            Algorithm::from_otpauth(Some("SHA512")),
+            Ok(Algorithm::Sha512)
+        );
+        assert_eq!(
+            Algorithm::from_otpauth(Some("sha512")),
+            Ok(Algorithm::Sha512)
+        );
+        assert_eq!(
+            Algorithm::from_otpauth(Some("SHA3")),
+            Err(GenerationError::UnknownAlgorithm("SHA3".into()))
+        );
+    }
+}
+
+#[cfg(test)]
+mod otp_trait_tests {
+    use crate::{Hotp, Otp, Totp};
+    use alloc::boxed::Box;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_dyn_otp() {
+        let key = "12345678901234567890";
+        let otps: Vec<(Box<dyn Otp>, u64)> = vec![
+            // RFC 4226 at counter 1, and RFC 6238 at T = 59 which is the same time step
+            (Box::new(Hotp::new()), 1),
+            (Box::new(Totp::new()), 59),
+        ];
+        for (otp, factor) in &otps {
+            assert_eq!(otp.generate(key, *factor).unwrap(), "287082");
+            assert!(otp.verify("287082", key, *factor).unwrap());
+            assert!(!otp.verify("287082", key, factor + 60).unwrap());
+        }
+    }
 }
 
 #[cfg(test)]
+mod format_code_tests {
+    use crate::format_code;
+
+    #[test]
+    fn test_format_code() {
+        assert_eq!(format_code("123456", 3, " "), "123 456");
+        assert_eq!(format_code("12345678", 4, "-"), "1234-5678");
+        assert_eq!(format_code("1234567", 3, " "), "123 456 7");
+        assert_eq!(format_code("123456", 0, " "), "123456");
+        assert_eq!(format_code("", 3, " "), "");
+    }
+}
+
+#[cfg(test)]
+mod counter_tests {
+    use crate::{counter_to_bytes, Counter, GenerationError};
+
+    #[test]
+    fn test_counter_is_big_endian() {
+        assert_eq!(counter_to_bytes(Counter(0)), [0; 8]);
+        assert_eq!(counter_to_bytes(Counter(1)), [0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(
+            counter_to_bytes(Counter(0x0102_0304_0506_0708)),
+            [1, 2, 3, 4, 5, 6, 7, 8]
+        );
+        assert_eq!(counter_to_bytes(Counter(u64::MAX)), [0xff; 8]);
+    }
+
+    #[test]
+    fn test_counter_from_unix_time() {
+        assert_eq!(Counter::from_unix_time(59, 0, 30), Ok(Counter(1)));
+        assert_eq!(
+            Counter::from_unix_time(1_111_111_109, 0, 30),
+            Ok(Counter(37037036))
+        );
+        assert_eq!(Counter::from_unix_time(90, 30, 30), Ok(Counter(2)));
+        assert_eq!(
+            Counter::from_unix_time(59, 0, 0),
+            Err(GenerationError::InvalidPeriod())
+        );
+        assert_eq!(
+            Counter::from_unix_time(100, 500, 30),
+            Err(GenerationError::TimeBeforeEpoch())
+        );
+        assert_eq!(Counter::from_unix_time(500, 500, 30), Ok(Counter(0)));
+        assert_eq!(u64::from(Counter::from(7)), 7);
+    }
+
+    #[test]
+    fn test_counter_matches_manual_encoding() {
+        let counter: u64 = 0x1234_5678_9abc_def0;
+        let mut manual = [0u8; 8];
+        for (i, byte) in manual.iter_mut().enumerate() {
+            *byte = ((counter >> (8 * (7 - i))) & 0xff) as u8;
+        }
+        assert_eq!(counter_to_bytes(Counter(counter)), manual);
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
 mod generate_secret_tests {
     use crate::{
-        generate_secret_ascii, generate_secret_without_symbols, generate_sized_secret, SYMBOL_SET,
+        estimate_secret_entropy, generate_secret_ascii, generate_secret_bytes,
+        generate_secret_bytes_with_rng, generate_secret_with_charset, generate_secret_with_rng,
+        generate_secret_without_symbols, generate_sized_secret, is_weak_secret,
+        secret_entropy_bits, GenerationError, CHAR_SET, SYMBOL_SET,
     };
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn test_generate_secret_bytes() {
+        let secret = generate_secret_bytes(20);
+        assert_eq!(secret.len(), 20);
+        assert_ne!(secret, generate_secret_bytes(20));
+        assert!(generate_secret_bytes(0).is_empty());
+    }
+
+    #[test]
+    fn test_generate_secret_with_seeded_rng() {
+        let mut rng = ChaCha20Rng::seed_from_u64(42);
+        let secret = generate_secret_with_rng(&mut rng, 16, false);
+        assert_eq!(secret.len(), 16);
+        assert_eq!(
+            secret,
+            generate_secret_with_rng(&mut ChaCha20Rng::seed_from_u64(42), 16, false)
+        );
+        assert_eq!(secret, "VP7AALpR4N7iwci5");
+
+        let bytes = generate_secret_bytes_with_rng(&mut ChaCha20Rng::seed_from_u64(42), 4);
+        assert_eq!(bytes, vec![120, 72, 181, 215]);
+    }
+
+    #[test]
+    fn test_generate_secret_with_charset() {
+        let charset = ['a', 'b', 'c', 'd'];
+        let secret = generate_secret_with_charset(200, &charset).unwrap();
+        assert_eq!(secret.len(), 200);
+        assert!(secret.chars().all(|c| charset.contains(&c)));
+        assert!(charset.iter().all(|c| secret.contains(*c)));
+        assert_eq!(
+            generate_secret_with_charset(10, &[]),
+            Err(GenerationError::EmptyCharset())
+        );
+    }
+
+    #[test]
+    fn test_generate_secret_with_two_character_charset() {
+        let secret = generate_secret_with_charset(500, &['x', 'y']).unwrap();
+        assert_eq!(secret.len(), 500);
+        assert!(secret.chars().all(|c| c == 'x' || c == 'y'));
+        assert!(secret.contains('x') && secret.contains('y'));
+    }
+
+    #[test]
+    fn test_secret_entropy_bits() {
+        assert!((secret_entropy_bits(32, 32) - 160.0).abs() < 1e-9);
+        assert!((secret_entropy_bits(32, CHAR_SET.len()) - 190.5).abs() < 0.1);
+        assert_eq!(secret_entropy_bits(20, 256), 160.0);
+        assert_eq!(secret_entropy_bits(10, 1), 0.0);
+    }
+
+    #[test]
+    fn test_estimate_secret_entropy() {
+        let random = generate_secret_bytes_with_rng(&mut ChaCha20Rng::seed_from_u64(42), 20);
+        assert!(estimate_secret_entropy(&random) > 64.0);
+        assert!(!is_weak_secret(&random));
+
+        let zeroes = [0u8; 20];
+        assert_eq!(estimate_secret_entropy(&zeroes), 0.0);
+        assert!(is_weak_secret(&zeroes));
+
+        let sequential: Vec<u8> = (0..20).collect();
+        assert_eq!(estimate_secret_entropy(&sequential), 0.0);
+        assert!(is_weak_secret(&sequential));
+        assert!(is_weak_secret(b"zyxwvutsrqponmlkjihg"));
+        assert!(is_weak_secret(&[]));
+    }
 
     #[test]
     fn test_generate_secret_ascii_no_symbols() {
@@ -297,7 +1447,7 @@ mod generate_secret_tests {
     fn test_generate_secret_ascii_symbols() {
         let secret = generate_secret_ascii(2000, true);
         assert_eq!(secret.len(), 2000);
-        assert_eq!(secret.contains("!"), true);
+        assert!(secret.contains("!"));
     }
 
     //    #[test]
@@ -321,14 +1471,119 @@ mod generate_secret_tests {
 
     #[test]
     fn test_generate_secret_non_default_symbols() {
+        assert!(!generate_secret_without_symbols()
+            .chars()
+            .any(|c| SYMBOL_SET.binary_search(&c).is_ok()))
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod base32_tests {
+    use crate::{validate_base32_secret, GenerationError};
+
+    #[test]
+    fn test_validate_base32_secret() {
+        let secret = b"SuperDuperSecretKey".to_vec();
+        assert_eq!(
+            validate_base32_secret("KN2XAZLSIR2XAZLSKNSWG4TFORFWK6I"),
+            Ok(secret.clone())
+        );
+        assert_eq!(
+            validate_base32_secret("kn2xazlsir2xazlsknswg4tforfwk6i"),
+            Ok(secret.clone())
+        );
         assert_eq!(
-            generate_secret_without_symbols()
-                .chars()
-                .any(|c| match SYMBOL_SET.binary_search(&c) {
-                    Ok(_) => true,
-                    _ => false,
-                }),
-            false
-        )
+            validate_base32_secret("KN2XAZLSIR2XAZLSKNSWG4TFORFWK6I="),
+            Ok(secret)
+        );
+    }
+
+    #[test]
+    fn test_validate_invalid_base32_secret() {
+        for secret in ["", "====", "KN2XAZ1S", "not base32!"] {
+            assert_eq!(
+                validate_base32_secret(secret),
+                Err(GenerationError::InvalidBase32()),
+                "{}",
+                secret
+            );
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod backup_code_tests {
+    use crate::{
+        generate_backup_codes, generate_grouped_backup_codes, hash_backup_code_with, pbkdf2_sha256,
+        verify_backup_code,
+    };
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_backup_codes_count_and_length() {
+        let codes = generate_backup_codes(10, 8);
+        assert_eq!(codes.len(), 10);
+        assert!(codes
+            .iter()
+            .all(|c| c.len() == 8 && c.chars().all(|d| d.is_ascii_digit())));
+    }
+
+    #[test]
+    fn test_backup_codes_are_unique() {
+        let codes = generate_backup_codes(200, 10);
+        let unique: HashSet<&String> = codes.iter().collect();
+        assert_eq!(unique.len(), codes.len());
+    }
+
+    #[test]
+    fn test_grouped_backup_codes() {
+        let codes = generate_grouped_backup_codes(5, 8, 4);
+        assert!(codes.iter().all(|c| c.len() == 9 && &c[4..5] == "-"));
+    }
+
+    #[test]
+    fn test_pbkdf2_sha256_vectors() {
+        // RFC 7914 section 11, truncated to one block
+        assert_eq!(
+            hex::encode(pbkdf2_sha256(b"passwd", b"salt", 1)),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc"
+        );
+        assert_eq!(
+            hex::encode(pbkdf2_sha256(b"password", b"salt", 4096)),
+            "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"
+        );
+    }
+
+    #[test]
+    fn test_hash_backup_code() {
+        let hash = hash_backup_code_with("1234-5678", b"0123456789abcdef", 10);
+        assert!(hash.starts_with("$pbkdf2-sha256$i=10$"));
+        assert!(verify_backup_code("1234-5678", &hash));
+        assert!(verify_backup_code("12345678", &hash));
+        assert!(!verify_backup_code("12345679", &hash));
+        assert_ne!(
+            hash,
+            hash_backup_code_with("1234-5678", b"fedcba9876543210", 10)
+        );
+    }
+
+    #[test]
+    fn test_verify_backup_code_malformed_hash() {
+        let hash = hash_backup_code_with("12345678", b"0123456789abcdef", 10);
+        assert!(!verify_backup_code("12345678", ""));
+        assert!(!verify_backup_code(
+            "12345678",
+            &hash.replace("i=10", "i=0")
+        ));
+        assert!(!verify_backup_code(
+            "12345678",
+            &hash.replace("i=10", &format!("i={}", u32::MAX))
+        ));
+        assert!(!verify_backup_code(
+            "12345678",
+            &hash.replace("sha256", "sha1")
+        ));
+        assert!(!verify_backup_code("12345678", &format!("{}$", hash)));
+        assert!(!verify_backup_code("12345678", &hash[..hash.len() - 2]));
     }
 }
@@ -0,0 +1,258 @@
+use percent_encoding::percent_decode_str;
+use url::Url;
+
+use crate::otpauth::OtpAuth;
+use crate::{Algorithm, GenerationError};
+
+/// Parse a Google Authenticator export (`otpauth-migration://offline?data=...`) into the
+/// accounts it contains.
+///
+/// The `data` parameter is a Base64 encoded `MigrationPayload` protobuf, holding one
+/// `OtpParameters` message per account. TOTP accounts always use a 30 second period, as the
+/// export format has no field for it.
+///
+/// # Arguments
+///
+/// * `url` - The `otpauth-migration://` URL, e.g. scanned from the export QR code
+///
+/// # Examples
+///
+/// ```
+/// use lugnut::migration::parse_migration_payload;
+/// let accounts = parse_migration_payload(
+///     "otpauth-migration://offline?data=ChUKBnNlY3JldBIFYWxpY2UgASgBMAIQARgBIAA%3D",
+/// )
+/// .expect("invalid export");
+/// assert_eq!(accounts[0].label, "alice");
+/// ```
+pub fn parse_migration_payload(url: &str) -> Result<Vec<OtpAuth>, GenerationError> {
+    let url = Url::parse(url).map_err(|_| GenerationError::InvalidOtpAuthUrl())?;
+    if url.scheme() != "otpauth-migration" {
+        return Err(GenerationError::InvalidOtpAuthUrl());
+    }
+    // Read the raw parameter, as form decoding would turn any unescaped '+' into a space
+    let data = url
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("data="))
+        .ok_or(GenerationError::InvalidOtpAuthUrl())?;
+    let data = percent_decode_str(data)
+        .decode_utf8()
+        .map_err(|_| GenerationError::InvalidMigrationPayload())?;
+    let payload =
+        base64::decode(data.as_ref()).map_err(|_| GenerationError::InvalidMigrationPayload())?;
+
+    let mut accounts = Vec::new();
+    let mut reader = ProtoReader::new(&payload);
+    while let Some((field, value)) = reader.next_field()? {
+        // Field 1 is the repeated otp_parameters, the rest are batch metadata
+        if let (1, ProtoValue::Bytes(parameters)) = (field, value) {
+            accounts.push(parse_otp_parameters(parameters)?);
+        }
+    }
+    Ok(accounts)
+}
+
+#[doc(hidden)]
+fn parse_otp_parameters(message: &[u8]) -> Result<OtpAuth, GenerationError> {
+    let mut name = String::new();
+    let mut otpauth = OtpAuth {
+        otp_type: "totp".to_string(),
+        label: String::new(),
+        issuer: None,
+        secret: Vec::new(),
+        algorithm: Algorithm::Sha1,
+        digits: 6,
+        period: 30,
+        counter: None,
+    };
+    let mut counter = 0;
+
+    let mut reader = ProtoReader::new(message);
+    while let Some((field, value)) = reader.next_field()? {
+        match (field, value) {
+            (1, ProtoValue::Bytes(secret)) => otpauth.secret = secret.to_vec(),
+            (2, ProtoValue::Bytes(bytes)) => name = utf8(bytes)?,
+            (3, ProtoValue::Bytes(bytes)) => {
+                let issuer = utf8(bytes)?;
+                if !issuer.is_empty() {
+                    otpauth.issuer = Some(issuer);
+                }
+            }
+            (4, ProtoValue::Varint(algorithm)) => {
+                otpauth.algorithm = match algorithm {
+                    0 | 1 => Algorithm::Sha1,
+                    2 => Algorithm::Sha256,
+                    3 => Algorithm::Sha512,
+                    4 => return Err(GenerationError::UnknownAlgorithm("MD5".to_string())),
+                    _ => return Err(GenerationError::InvalidMigrationPayload()),
+                }
+            }
+            (5, ProtoValue::Varint(digits)) => {
+                otpauth.digits = match digits {
+                    0 | 1 => 6,
+                    2 => 8,
+                    _ => return Err(GenerationError::InvalidMigrationPayload()),
+                }
+            }
+            (6, ProtoValue::Varint(otp_type)) => {
+                otpauth.otp_type = match otp_type {
+                    0 | 2 => "totp",
+                    1 => "hotp",
+                    _ => return Err(GenerationError::InvalidMigrationPayload()),
+                }
+                .to_string()
+            }
+            (7, ProtoValue::Varint(value)) => counter = value,
+            _ => {}
+        }
+    }
+
+    // Like otpauth labels, the name may be prefixed with the issuer
+    match name.split_once(':') {
+        Some((issuer, label)) => {
+            if otpauth.issuer.is_none() {
+                otpauth.issuer = Some(issuer.to_string());
+            }
+            otpauth.label = label.trim_start().to_string();
+        }
+        None => otpauth.label = name,
+    }
+    if otpauth.otp_type == "hotp" {
+        otpauth.counter = Some(counter);
+    }
+    if otpauth.secret.is_empty() {
+        return Err(GenerationError::InvalidMigrationPayload());
+    }
+    Ok(otpauth)
+}
+
+#[doc(hidden)]
+fn utf8(bytes: &[u8]) -> Result<String, GenerationError> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| GenerationError::InvalidMigrationPayload())
+}
+
+enum ProtoValue<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// A minimal protobuf wire format reader, just enough for the migration payload.
+struct ProtoReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ProtoReader<'a> {
+    fn new(buf: &'a [u8]) -> ProtoReader<'a> {
+        ProtoReader { buf, pos: 0 }
+    }
+
+    fn next_field(&mut self) -> Result<Option<(u64, ProtoValue<'a>)>, GenerationError> {
+        if self.pos == self.buf.len() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let value = match key & 0x7 {
+            0 => ProtoValue::Varint(self.varint()?),
+            1 => {
+                self.take(8)?;
+                ProtoValue::Fixed
+            }
+            2 => {
+                let len = self.varint()? as usize;
+                ProtoValue::Bytes(self.take(len)?)
+            }
+            5 => {
+                self.take(4)?;
+                ProtoValue::Fixed
+            }
+            _ => return Err(GenerationError::InvalidMigrationPayload()),
+        };
+        Ok(Some((key >> 3, value)))
+    }
+
+    fn varint(&mut self) -> Result<u64, GenerationError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = *self
+                .buf
+                .get(self.pos)
+                .ok_or(GenerationError::InvalidMigrationPayload())?;
+            self.pos += 1;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(GenerationError::InvalidMigrationPayload())
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], GenerationError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.buf.len())
+            .ok_or(GenerationError::InvalidMigrationPayload())?;
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod migration_tests {
+    use super::parse_migration_payload;
+    use crate::{Algorithm, GenerationError};
+
+    // Two accounts: a TOTP "Example:alice@example.com" and an 8 digit SHA256 HOTP "bob"
+    const EXPORT: &str = "otpauth-migration://offline?data=CjYKCkhlbGxvId6tvu8SGUV4YW1wbGU6YWx\
+        pY2VAZXhhbXBsZS5jb20aB0V4YW1wbGUgASgBMAIKIwoUMTIzNDU2Nzg5MDEyMzQ1Njc4OTASA2JvYiACKAIwAT\
+        gHEAEYASAAKP%2F%2F%2F%2F%2F%2F%2F%2F%2F%2F%2FwE%3D";
+
+    #[test]
+    fn test_parse_two_accounts() {
+        let accounts = parse_migration_payload(EXPORT).unwrap();
+        assert_eq!(accounts.len(), 2);
+
+        assert_eq!(accounts[0].otp_type, "totp");
+        assert_eq!(accounts[0].label, "alice@example.com");
+        assert_eq!(accounts[0].issuer.as_deref(), Some("Example"));
+        assert_eq!(accounts[0].secret, b"Hello!\xde\xad\xbe\xef");
+        assert_eq!(accounts[0].algorithm, Algorithm::Sha1);
+        assert_eq!(accounts[0].digits, 6);
+        assert_eq!(accounts[0].period, 30);
+        assert_eq!(accounts[0].counter, None);
+
+        assert_eq!(accounts[1].otp_type, "hotp");
+        assert_eq!(accounts[1].label, "bob");
+        assert_eq!(accounts[1].issuer, None);
+        assert_eq!(accounts[1].secret, b"12345678901234567890");
+        assert_eq!(accounts[1].algorithm, Algorithm::Sha256);
+        assert_eq!(accounts[1].digits, 8);
+        assert_eq!(accounts[1].counter, Some(7));
+    }
+
+    #[test]
+    fn test_parse_invalid_payloads() {
+        assert_eq!(
+            parse_migration_payload("otpauth://totp/alice?secret=ONSWG4TFOQ"),
+            Err(GenerationError::InvalidOtpAuthUrl())
+        );
+        assert_eq!(
+            parse_migration_payload("otpauth-migration://offline"),
+            Err(GenerationError::InvalidOtpAuthUrl())
+        );
+        assert_eq!(
+            parse_migration_payload("otpauth-migration://offline?data=!!!"),
+            Err(GenerationError::InvalidMigrationPayload())
+        );
+        // Truncated: claims a 54 byte message but only has 2 bytes
+        assert_eq!(
+            parse_migration_payload("otpauth-migration://offline?data=CjYKCg%3D%3D"),
+            Err(GenerationError::InvalidMigrationPayload())
+        );
+    }
+}
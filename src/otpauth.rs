@@ -0,0 +1,454 @@
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use url::Url;
+
+use crate::{validate_base32_secret, Algorithm, GenerationError};
+
+/// Builds otpauth Key URIs (`otpauth://TYPE/LABEL?secret=...`) as understood by authenticator apps.
+///
+/// The label defaults to the `Issuer:account` form recommended by the Key URI format, and the
+/// `issuer` query parameter is always sent.
+pub struct OtpAuthUrlBuilder {
+    otp_type: String,
+    issuer_in_label: bool,
+    parameters: Vec<(String, String)>,
+}
+
+impl OtpAuthUrlBuilder {
+    /// Returns a new instance of an otpauth URL Builder.
+    ///
+    /// # Arguments
+    ///
+    /// * `otp_type` - Either `totp` or `hotp`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::otpauth::OtpAuthUrlBuilder;
+    /// let mut url_builder = OtpAuthUrlBuilder::new("totp");
+    /// ```
+    pub fn new(otp_type: &str) -> OtpAuthUrlBuilder {
+        OtpAuthUrlBuilder {
+            otp_type: otp_type.to_string(),
+            issuer_in_label: true,
+            parameters: Vec::new(),
+        }
+    }
+
+    /// Set whether the issuer is prefixed to the label (`Issuer:account`) or the label is just
+    /// the account name. The `issuer` query parameter is sent either way.
+    ///
+    /// Defaults to true.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::otpauth::OtpAuthUrlBuilder;
+    /// let mut url_builder = OtpAuthUrlBuilder::new("totp");
+    /// url_builder.with_issuer_in_label(false);
+    /// ```
+    pub fn with_issuer_in_label(&mut self, issuer_in_label: bool) -> &mut OtpAuthUrlBuilder {
+        self.issuer_in_label = issuer_in_label;
+        self
+    }
+
+    /// Append an extra query parameter, such as `digits` or `period`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::otpauth::OtpAuthUrlBuilder;
+    /// let mut url_builder = OtpAuthUrlBuilder::new("totp");
+    /// url_builder.with_parameter("period", "30");
+    /// ```
+    pub fn with_parameter(&mut self, name: &str, value: &str) -> &mut OtpAuthUrlBuilder {
+        self.parameters.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Build the URL. The secret is Base32 encoded without padding.
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - The account name, e.g. `alice@example.com`
+    /// * `issuer` - The provider or service the account belongs to
+    /// * `secret` - The secret key
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::otpauth::OtpAuthUrlBuilder;
+    /// let url = OtpAuthUrlBuilder::new("totp").build("alice@example.com", "Example", "my secret key");
+    /// ```
+    pub fn build(&self, label: &str, issuer: &str, secret: &str) -> String {
+        self.build_from_bytes(label, issuer, secret.as_bytes())
+    }
+
+    /// Build the URL from a raw secret key, such as one decoded from hex. The secret is Base32
+    /// encoded without padding.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::otpauth::OtpAuthUrlBuilder;
+    /// let url = OtpAuthUrlBuilder::new("totp")
+    ///     .build_from_bytes("alice@example.com", "Example", &[0xde, 0xad, 0xbe, 0xef]);
+    /// ```
+    pub fn build_from_bytes(&self, label: &str, issuer: &str, secret: &[u8]) -> String {
+        let mut url = format!("otpauth://{}/", self.otp_type);
+        if self.issuer_in_label {
+            url.push_str(&encode_uri_component(issuer));
+            url.push(':');
+        }
+        url.push_str(&encode_uri_component(label));
+        url.push_str(&format!(
+            "?secret={}&issuer={}",
+            base32::encode(base32::Alphabet::RFC4648 { padding: false }, secret),
+            encode_uri_component(issuer),
+        ));
+        for (name, value) in &self.parameters {
+            url.push_str(&format!("&{}={}", name, encode_uri_component(value)));
+        }
+        url
+    }
+}
+
+/// The account parameters carried by an otpauth Key URI, as returned by `parse_otpauth_url`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OtpAuth {
+    /// Either `totp` or `hotp`
+    pub otp_type: String,
+    /// The account name, without any `Issuer:` prefix
+    pub label: String,
+    /// The `issuer` parameter, falling back to the label prefix
+    pub issuer: Option<String>,
+    /// The Base32 decoded secret key
+    pub secret: Vec<u8>,
+    /// Defaults to SHA1
+    pub algorithm: Algorithm,
+    /// Defaults to 6
+    pub digits: u32,
+    /// The TOTP step in seconds, defaults to 30
+    pub period: u64,
+    /// The initial HOTP counter, required for `hotp` URLs
+    pub counter: Option<u64>,
+}
+
+/// Parse an otpauth Key URI, e.g. one scanned from an enrollment QR code.
+///
+/// Parameters that are present but malformed are reported rather than replaced by their
+/// defaults: `digits` other than 6, 7 or 8 fail with `GenerationError::InvalidDigits`, an
+/// unknown `algorithm` with `GenerationError::UnknownAlgorithm` and a `period` of 0 with
+/// `GenerationError::InvalidPeriod`. Anything else that doesn't fit the format fails with
+/// `GenerationError::InvalidOtpAuthUrl`.
+///
+/// # Arguments
+///
+/// * `url` - The `otpauth://` URL
+///
+/// # Examples
+///
+/// ```
+/// use lugnut::otpauth::parse_otpauth_url;
+/// let otpauth = parse_otpauth_url("otpauth://totp/Example:alice@example.com?secret=ONSWG4TFOQ")
+///     .expect("invalid url");
+/// assert_eq!(otpauth.label, "alice@example.com");
+/// assert_eq!(otpauth.secret, b"secret");
+/// ```
+pub fn parse_otpauth_url(url: &str) -> Result<OtpAuth, GenerationError> {
+    let url = Url::parse(url).map_err(|_| GenerationError::InvalidOtpAuthUrl())?;
+    if url.scheme() != "otpauth" {
+        return Err(GenerationError::InvalidOtpAuthUrl());
+    }
+    let otp_type = match url.host_str().map(|t| t.to_ascii_lowercase()) {
+        Some(t) if t == "totp" || t == "hotp" => t,
+        _ => return Err(GenerationError::InvalidOtpAuthUrl()),
+    };
+
+    // Split on the literal delimiter before decoding, so an encoded colon inside the issuer
+    // stays part of it. Only a label without one falls back to an encoded `%3A` delimiter.
+    let path = url.path().trim_start_matches('/');
+    let (label_issuer, label) = match path.split_once(':') {
+        Some((issuer, label)) => (Some(decode_label(issuer)?), decode_label(label)?),
+        None => {
+            let path = decode_label(path)?;
+            match path.split_once(':') {
+                Some((issuer, label)) => (Some(issuer.to_string()), label.to_string()),
+                None => (None, path),
+            }
+        }
+    };
+    let label = label.trim_start().to_string();
+    if label.is_empty() {
+        return Err(GenerationError::InvalidOtpAuthUrl());
+    }
+
+    let mut algorithm = None;
+    let mut otpauth = OtpAuth {
+        otp_type,
+        label,
+        issuer: label_issuer,
+        secret: Vec::new(),
+        algorithm: Algorithm::Sha1,
+        digits: 6,
+        period: 30,
+        counter: None,
+    };
+    for (name, value) in url.query_pairs() {
+        match name.as_ref() {
+            "secret" => otpauth.secret = validate_base32_secret(&value)?,
+            "issuer" => otpauth.issuer = Some(value.into_owned()),
+            "algorithm" => algorithm = Some(value),
+            "digits" => {
+                let digits = parse_number(&value)?;
+                if !(6..=8).contains(&digits) {
+                    return Err(GenerationError::InvalidDigits(digits));
+                }
+                otpauth.digits = digits;
+            }
+            "period" => {
+                otpauth.period = parse_number(&value)?;
+                if otpauth.period == 0 {
+                    return Err(GenerationError::InvalidPeriod());
+                }
+            }
+            "counter" => otpauth.counter = Some(parse_number(&value)?),
+            _ => {}
+        }
+    }
+
+    otpauth.algorithm = Algorithm::from_otpauth(algorithm.as_deref())?;
+    if otpauth.secret.is_empty() || (otpauth.otp_type == "hotp" && otpauth.counter.is_none()) {
+        return Err(GenerationError::InvalidOtpAuthUrl());
+    }
+    Ok(otpauth)
+}
+
+#[doc(hidden)]
+fn parse_number<T: core::str::FromStr>(value: &str) -> Result<T, GenerationError> {
+    value
+        .parse()
+        .map_err(|_| GenerationError::InvalidOtpAuthUrl())
+}
+
+/// The characters JavaScript's `encodeURIComponent` leaves as they are. Everything else,
+/// including the `:` label delimiter, is percent-encoded.
+const URI_COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'!')
+    .remove(b'~')
+    .remove(b'*')
+    .remove(b'\'')
+    .remove(b'(')
+    .remove(b')');
+
+/// Percent-encodes a label or parameter value like `encodeURIComponent`. Spaces become `%20`
+/// rather than `+`, which some authenticator apps show literally in the label.
+#[doc(hidden)]
+fn decode_label(part: &str) -> Result<String, GenerationError> {
+    percent_decode_str(part)
+        .decode_utf8()
+        .map(|part| part.into_owned())
+        .map_err(|_| GenerationError::InvalidOtpAuthUrl())
+}
+
+#[doc(hidden)]
+fn encode_uri_component(string: &str) -> String {
+    utf8_percent_encode(string, URI_COMPONENT).to_string()
+}
+
+/// Renders an otpauth URL as an SVG QR code for enrollment.
+#[cfg(feature = "qrcode")]
+#[doc(hidden)]
+pub(crate) fn generate_qr_svg(url: &str) -> Result<String, GenerationError> {
+    let code = qrcode::QrCode::new(url.as_bytes())
+        .map_err(|_| GenerationError::FailedToGenerateQrCode())?;
+    Ok(code.render::<qrcode::render::svg::Color>().build())
+}
+
+#[cfg(test)]
+mod otpauth_url_tests {
+    use super::OtpAuthUrlBuilder;
+
+    #[test]
+    fn test_issuer_in_label() {
+        let url = OtpAuthUrlBuilder::new("totp").build("alice@example.com", "Example", "secret");
+        assert_eq!(
+            url,
+            "otpauth://totp/Example:alice%40example.com?secret=ONSWG4TFOQ&issuer=Example"
+        );
+    }
+
+    #[test]
+    fn test_issuer_not_in_label() {
+        let url = OtpAuthUrlBuilder::new("totp")
+            .with_issuer_in_label(false)
+            .build("alice@example.com", "Example", "secret");
+        assert_eq!(
+            url,
+            "otpauth://totp/alice%40example.com?secret=ONSWG4TFOQ&issuer=Example"
+        );
+    }
+
+    #[test]
+    fn test_colon_in_issuer_is_encoded() {
+        let url = OtpAuthUrlBuilder::new("totp").build("alice", "Big:Corp", "secret");
+        assert!(url.starts_with("otpauth://totp/Big%3ACorp:alice?"));
+    }
+
+    #[test]
+    fn test_space_in_issuer_and_label_is_encoded() {
+        let url = OtpAuthUrlBuilder::new("totp").build("alice smith", "Big Corp", "secret");
+        assert_eq!(
+            url,
+            "otpauth://totp/Big%20Corp:alice%20smith?secret=ONSWG4TFOQ&issuer=Big%20Corp"
+        );
+    }
+
+    #[test]
+    fn test_colon_and_space_in_issuer_query_is_encoded() {
+        let url = OtpAuthUrlBuilder::new("totp").build("alice", "Big Corp: Prod", "secret");
+        assert_eq!(
+            url,
+            "otpauth://totp/Big%20Corp%3A%20Prod:alice?secret=ONSWG4TFOQ&issuer=Big%20Corp%3A%20Prod"
+        );
+    }
+
+    #[test]
+    fn test_emoji_in_issuer_is_encoded() {
+        let url = OtpAuthUrlBuilder::new("totp").build("alice", "Rocket \u{1F680}", "secret");
+        assert_eq!(
+            url,
+            "otpauth://totp/Rocket%20%F0%9F%9A%80:alice?secret=ONSWG4TFOQ&issuer=Rocket%20%F0%9F%9A%80"
+        );
+    }
+
+    #[test]
+    fn test_parameters() {
+        let url = OtpAuthUrlBuilder::new("hotp")
+            .with_parameter("counter", "5")
+            .build("alice", "Example", "secret");
+        assert!(url.ends_with("&issuer=Example&counter=5"));
+    }
+}
+
+#[cfg(test)]
+mod parse_otpauth_url_tests {
+    use super::{parse_otpauth_url, OtpAuth, OtpAuthUrlBuilder};
+    use crate::{Algorithm, GenerationError};
+
+    const BASE: &str = "otpauth://totp/Example:alice%40example.com?secret=ONSWG4TFOQ";
+
+    #[test]
+    fn test_parse_defaults() {
+        assert_eq!(
+            parse_otpauth_url(BASE),
+            Ok(OtpAuth {
+                otp_type: "totp".to_string(),
+                label: "alice@example.com".to_string(),
+                issuer: Some("Example".to_string()),
+                secret: b"secret".to_vec(),
+                algorithm: Algorithm::Sha1,
+                digits: 6,
+                period: 30,
+                counter: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_round_trip_colons_and_spaces() {
+        let url = OtpAuthUrlBuilder::new("totp").build(
+            "alice smith: work",
+            "Big Corp: Prod",
+            "SuperDuperSecretKey",
+        );
+        let otpauth = parse_otpauth_url(&url).unwrap();
+        assert_eq!(otpauth.label, "alice smith: work");
+        assert_eq!(otpauth.issuer.as_deref(), Some("Big Corp: Prod"));
+    }
+
+    #[test]
+    fn test_parse_encoded_label_delimiter() {
+        let otpauth =
+            parse_otpauth_url("otpauth://totp/Example%3Aalice?secret=ONSWG4TFOQ").unwrap();
+        assert_eq!(otpauth.issuer.as_deref(), Some("Example"));
+        assert_eq!(otpauth.label, "alice");
+    }
+
+    #[test]
+    fn test_parse_round_trip() {
+        let url = OtpAuthUrlBuilder::new("hotp")
+            .with_parameter("algorithm", "SHA512")
+            .with_parameter("digits", "8")
+            .with_parameter("counter", "50")
+            .build("alice@example.com", "Big Corp", "SuperDuperSecretKey");
+        let otpauth = parse_otpauth_url(&url).unwrap();
+        assert_eq!(otpauth.otp_type, "hotp");
+        assert_eq!(otpauth.label, "alice@example.com");
+        assert_eq!(otpauth.issuer.as_deref(), Some("Big Corp"));
+        assert_eq!(otpauth.secret, b"SuperDuperSecretKey");
+        assert_eq!(otpauth.algorithm, Algorithm::Sha512);
+        assert_eq!(otpauth.digits, 8);
+        assert_eq!(otpauth.counter, Some(50));
+    }
+
+    #[test]
+    fn test_parse_invalid_digits() {
+        assert_eq!(
+            parse_otpauth_url(&format!("{}&digits=5", BASE)),
+            Err(GenerationError::InvalidDigits(5))
+        );
+        assert_eq!(
+            parse_otpauth_url(&format!("{}&digits=10", BASE)),
+            Err(GenerationError::InvalidDigits(10))
+        );
+        assert_eq!(
+            parse_otpauth_url(&format!("{}&digits=six", BASE)),
+            Err(GenerationError::InvalidOtpAuthUrl())
+        );
+    }
+
+    #[test]
+    fn test_parse_unknown_algorithm() {
+        assert_eq!(
+            parse_otpauth_url(&format!("{}&algorithm=MD5", BASE)),
+            Err(GenerationError::UnknownAlgorithm("MD5".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_secret() {
+        assert_eq!(
+            parse_otpauth_url("otpauth://totp/alice?secret=not-base32!"),
+            Err(GenerationError::InvalidBase32())
+        );
+    }
+
+    #[test]
+    fn test_parse_zero_period() {
+        assert_eq!(
+            parse_otpauth_url(&format!("{}&period=0", BASE)),
+            Err(GenerationError::InvalidPeriod())
+        );
+    }
+
+    #[test]
+    fn test_parse_malformed() {
+        for url in [
+            "https://totp/alice?secret=ONSWG4TFOQ",
+            "otpauth://motp/alice?secret=ONSWG4TFOQ",
+            "otpauth://totp/alice",
+            "otpauth://hotp/alice?secret=ONSWG4TFOQ",
+            "otpauth://totp/?secret=ONSWG4TFOQ",
+        ] {
+            assert_eq!(
+                parse_otpauth_url(url),
+                Err(GenerationError::InvalidOtpAuthUrl()),
+                "{}",
+                url
+            );
+        }
+    }
+}
@@ -0,0 +1,65 @@
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+
+/// Records the last counter consumed per user, so a valid code can't be used twice.
+///
+/// A TOTP code stays valid for its whole step (and any window), so without this a code observed
+/// in transit could be replayed until it expires. See `Totp::verify_once`.
+pub trait ReplayGuard {
+    /// Returns true and records `counter` as consumed for `user` if it is later than the last
+    /// counter consumed for them, otherwise returns false.
+    fn check_and_consume(&mut self, user: &str, counter: u64) -> bool;
+}
+
+/// An in-memory `ReplayGuard`, for single process servers and tests. Anything that runs more
+/// than one process should implement `ReplayGuard` over its shared user store instead.
+///
+/// # Examples
+///
+/// ```
+/// use lugnut::replay::{MemoryReplayGuard, ReplayGuard};
+/// let mut guard = MemoryReplayGuard::new();
+/// assert!(guard.check_and_consume("alice", 5));
+/// assert!(!guard.check_and_consume("alice", 5));
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+pub struct MemoryReplayGuard {
+    last_counters: HashMap<String, u64>,
+}
+
+#[cfg(feature = "std")]
+impl MemoryReplayGuard {
+    /// Returns a new guard with no consumed counters.
+    pub fn new() -> MemoryReplayGuard {
+        MemoryReplayGuard::default()
+    }
+}
+
+#[cfg(feature = "std")]
+impl ReplayGuard for MemoryReplayGuard {
+    fn check_and_consume(&mut self, user: &str, counter: u64) -> bool {
+        match self.last_counters.get(user) {
+            Some(last) if counter <= *last => false,
+            _ => {
+                self.last_counters.insert(user.to_string(), counter);
+                true
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod memory_replay_guard_tests {
+    use super::{MemoryReplayGuard, ReplayGuard};
+
+    #[test]
+    fn test_counters_are_consumed_per_user() {
+        let mut guard = MemoryReplayGuard::new();
+        assert!(guard.check_and_consume("alice", 5));
+        assert!(!guard.check_and_consume("alice", 5));
+        assert!(!guard.check_and_consume("alice", 4));
+        assert!(guard.check_and_consume("bob", 5));
+        assert!(guard.check_and_consume("alice", 6));
+    }
+}
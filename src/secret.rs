@@ -0,0 +1,65 @@
+use alloc::vec::Vec;
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+/// Key material stored by `Hotp`/`Totp`.
+///
+/// With the `zeroize` feature enabled the bytes are wiped when the secret is replaced or dropped,
+/// so they don't linger in freed memory of long-lived processes.
+#[derive(Default)]
+pub(crate) struct Secret(Vec<u8>);
+
+impl Secret {
+    /// An empty secret that can be extended to `capacity` bytes without reallocating, which
+    /// would leave an unwiped copy behind.
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Secret(Vec::with_capacity(capacity))
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub(crate) fn extend_from_slice(&mut self, data: &[u8]) {
+        self.0.extend_from_slice(data);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for Secret {
+    fn from(bytes: Vec<u8>) -> Self {
+        Secret(bytes)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for Secret {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+#[cfg(all(test, feature = "zeroize"))]
+mod secret_tests {
+    use super::Secret;
+    use zeroize::Zeroize;
+
+    #[test]
+    fn test_wiped_secret_has_no_plaintext() {
+        let mut secret = Secret::from(b"SuperDuperSecretKey".to_vec());
+        let (ptr, capacity) = (secret.0.as_ptr(), secret.0.capacity());
+
+        // Run the same wipe as `Drop`, but keep the allocation alive so it can be inspected
+        secret.0.zeroize();
+        assert!(secret.is_empty());
+        let wiped = unsafe { core::slice::from_raw_parts(ptr, capacity) };
+        assert!(wiped.iter().all(|b| *b == 0));
+    }
+}
@@ -1,13 +1,55 @@
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use alloc::string::String;
+use alloc::vec::Vec;
+#[cfg(any(feature = "time", feature = "chrono"))]
+use core::convert::TryFrom;
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::{digest, generate_otp, verify_delta, Algorithm, GenerationError};
+#[cfg(feature = "qrcode")]
+use crate::otpauth::generate_qr_svg;
+#[cfg(feature = "std")]
+use crate::otpauth::OtpAuthUrlBuilder;
+use crate::secret::Secret;
+use crate::{
+    check_key_length, check_token_length, digest_bytes, find_counter, find_delta, generate_otp,
+    numeric_token, verify_delta, Algorithm, Counter, GenerationError, Otp, ReplayGuard,
+    DEFAULT_MIN_KEY_LENGTH,
+};
+
+/// A point in time from a date and time library that can be converted to Unix seconds, see
+/// `Totp::generate_at_datetime` and `Totp::verify_at_datetime`.
+#[cfg(any(feature = "time", feature = "chrono"))]
+pub trait ToUnixTime {
+    /// Seconds since the Unix epoch, failing with `GenerationError::TimeBeforeEpoch` for earlier
+    /// times.
+    fn to_unix_time(&self) -> core::result::Result<u64, GenerationError>;
+}
+
+#[cfg(feature = "time")]
+impl ToUnixTime for time::OffsetDateTime {
+    fn to_unix_time(&self) -> core::result::Result<u64, GenerationError> {
+        u64::try_from(self.unix_timestamp()).map_err(|_| GenerationError::TimeBeforeEpoch())
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl<Tz: chrono::TimeZone> ToUnixTime for chrono::DateTime<Tz> {
+    fn to_unix_time(&self) -> core::result::Result<u64, GenerationError> {
+        u64::try_from(self.timestamp()).map_err(|_| GenerationError::TimeBeforeEpoch())
+    }
+}
 
 pub struct Totp {
     epoch_time_offset: u64,
     time: u64,
     step: u64,
-    window: u64,
+    window_back: u64,
+    window_forward: u64,
+    digits: u32,
+    algorithm: Algorithm,
     digest: Vec<u8>,
+    secret: Secret,
+    min_key_length: usize,
 }
 
 impl Totp {
@@ -25,14 +67,56 @@ impl Totp {
     /// ```
     pub fn new() -> Totp {
         Totp {
-            window: 0,
+            window_back: 0,
+            window_forward: 0,
             epoch_time_offset: 0,
             time: 0,
             step: 30,
+            digits: 6,
+            algorithm: Algorithm::Sha1,
             digest: Vec::new(),
+            secret: Secret::default(),
+            min_key_length: DEFAULT_MIN_KEY_LENGTH,
         }
     }
 
+    /// Returns a new TOTP configured by the parameters RFC 6238 defines TOTP with.
+    /// This makes it straightforward to reproduce the RFC 6238 Appendix B test vectors.
+    ///
+    /// # Arguments
+    ///
+    /// * `algorithm` - The HMAC hash function (`HMAC-SHA-1`, `HMAC-SHA-256` or `HMAC-SHA-512`)
+    /// * `digits` - The number of digits in the generated code
+    /// * `x` - The time step X in seconds
+    /// * `t0` - The Unix time T0 to start counting time steps from
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::{ Algorithm, Totp };
+    /// let totp = Totp::rfc6238(Algorithm::Sha512, 8, 30, 0);
+    /// ```
+    pub fn rfc6238(algorithm: Algorithm, digits: u32, x: u64, t0: u64) -> Totp {
+        let mut totp = Totp::new();
+        totp.with_algorithm(algorithm)
+            .with_length(digits)
+            .with_step(x)
+            .with_epoch_time_offset(t0);
+        totp
+    }
+
+    /// Returns a consuming TOTP Builder, for configuring a `Totp` in a single expression.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::totp::Totp;
+    /// let totp = Totp::builder().window(2).step(60).build();
+    /// ```
+    pub fn builder() -> TotpBuilder {
+        TotpBuilder { totp: Totp::new() }
+    }
+
     /// Set an epoch time offset to be used when calculating the time-based counter.
     /// Defaults to 0/
     ///
@@ -47,11 +131,91 @@ impl Totp {
     /// let mut totp_builder = Totp::new();
     /// totp_builder.with_epoch_time_offset(500);
     /// ```
-    pub fn with_epoch_time_offset<'a>(&'a mut self, offset: u64) -> &'a mut Totp {
+    pub fn with_epoch_time_offset(&mut self, offset: u64) -> &mut Totp {
         self.epoch_time_offset = offset;
         self
     }
 
+    /// Pin the Unix time (in seconds) used to calculate the time-based counter instead of
+    /// reading the system clock.
+    /// Defaults to 0, which uses the system clock. Without the `std` feature there is no
+    /// system clock, so a time must be set before generating or verifying.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - Unix time in seconds
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::totp::Totp;
+    /// let mut totp_builder = Totp::new();
+    /// totp_builder.with_time(1_111_111_109);
+    /// ```
+    pub fn with_time(&mut self, time: u64) -> &mut Totp {
+        self.time = time;
+        self
+    }
+
+    /// Set the time step in seconds used to calculate the time-based counter.
+    /// Defaults to 30. A step of 0 fails with `GenerationError::InvalidPeriod` when generating
+    /// or verifying.
+    ///
+    /// # Arguments
+    ///
+    /// * `step` - The time step in seconds
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::totp::Totp;
+    /// let mut totp_builder = Totp::new();
+    /// totp_builder.with_step(60);
+    /// ```
+    pub fn with_step(&mut self, step: u64) -> &mut Totp {
+        self.step = step;
+        self
+    }
+
+    /// Set the number of digits in the generated code, between 1 and `MAX_DIGITS`.
+    /// Out of range lengths fail with `GenerationError::InvalidDigits` when generating.
+    /// Defaults to 6.
+    ///
+    /// # Arguments
+    ///
+    /// * `digits` - The code length
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::totp::Totp;
+    /// let mut totp_builder = Totp::new();
+    /// totp_builder.with_length(8);
+    /// ```
+    pub fn with_length(&mut self, digits: u32) -> &mut Totp {
+        self.digits = digits;
+        self
+    }
+
+    /// Set the HMAC algorithm used to generate the code.
+    /// Defaults to SHA-1.
+    ///
+    /// # Arguments
+    ///
+    /// * `algorithm` - The HMAC algorithm
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::{ Algorithm, Totp };
+    /// let mut totp_builder = Totp::new();
+    /// totp_builder.with_algorithm(Algorithm::Sha256);
+    /// ```
+    pub fn with_algorithm(&mut self, algorithm: Algorithm) -> &mut Totp {
+        self.algorithm = algorithm;
+        self
+    }
+
     /// Set the window that will be checked when verifying the OTP.
     /// The window is two-sided, so if the window is set to 5, and the OTP is
     /// counter is 15, 10-20 will be asserted against while verifying.
@@ -68,12 +232,34 @@ impl Totp {
     /// let mut totp_builder = Totp::new();
     /// totp_builder.with_window(5);
     /// ```
-    pub fn with_window<'a>(&'a mut self, window: u64) -> &'a mut Totp {
-        self.window = window;
+    pub fn with_window(&mut self, window: u64) -> &mut Totp {
+        self.with_window_asymmetric(window, window)
+    }
+
+    /// Set separate windows for codes from past and future steps, e.g. to accept more past codes
+    /// when client clocks tend to run behind. With a counter of 15, `back` 2 and `forward` 1,
+    /// 13-16 will be asserted against while verifying.
+    ///
+    /// # Arguments
+    ///
+    /// * `back` - The number of past steps to accept
+    /// * `forward` - The number of future steps to accept
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::totp::Totp;
+    /// let mut totp_builder = Totp::new();
+    /// totp_builder.with_window_asymmetric(2, 0);
+    /// ```
+    pub fn with_window_asymmetric(&mut self, back: u64, forward: u64) -> &mut Totp {
+        self.window_back = back;
+        self.window_forward = forward;
         self
     }
 
-    /// Use a self-generated digest.
+    /// Use a self-generated digest. It must be at least `MIN_DIGEST_LENGTH` bytes, shorter
+    /// digests fail with `GenerationError::InvalidDigestLength` when generating.
     ///
     /// # Arguments
     ///
@@ -84,31 +270,174 @@ impl Totp {
     /// ```
     /// use lugnut::totp::Totp;
     /// let mut totp_builder = Totp::new();
-    /// totp_builder.with_digest(vec![1, 2, 3, 4]);
+    /// totp_builder.with_digest(vec![1; 20]);
     /// ```
-    pub fn with_digest<'a>(&'a mut self, digest: Vec<u8>) -> &'a mut Totp {
+    pub fn with_digest(&mut self, digest: Vec<u8>) -> &mut Totp {
         self.digest = digest;
         self
     }
 
+    /// Set the minimum accepted key length in bytes. Keys shorter than this fail with
+    /// `GenerationError::KeyTooShort`.
+    /// Defaults to `DEFAULT_MIN_KEY_LENGTH` (16 bytes).
+    ///
+    /// # Arguments
+    ///
+    /// * `min` - The minimum key length in bytes
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::totp::Totp;
+    /// let mut totp_builder = Totp::new();
+    /// totp_builder.with_min_key_length(20);
+    /// ```
+    pub fn with_min_key_length(&mut self, min: usize) -> &mut Totp {
+        self.min_key_length = min;
+        self
+    }
+
+    /// Use a hex encoded secret instead of the `key` passed to `generate`/`verify`.
+    /// The hex is decoded to raw key bytes before keying the HMAC, which is how the
+    /// RFC 6238 test vectors are specified.
+    ///
+    /// # Arguments
+    ///
+    /// * `hex` - The hex encoded secret
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::totp::Totp;
+    /// let mut totp_builder = Totp::new();
+    /// totp_builder
+    ///     .with_secret_hex("3132333435363738393031323334353637383930")
+    ///     .expect("invalid hex");
+    /// ```
+    pub fn with_secret_hex(
+        &mut self,
+        hex: &str,
+    ) -> core::result::Result<&mut Totp, GenerationError> {
+        self.secret = hex::decode(hex)
+            .map_err(|_| GenerationError::InvalidHexSecret())?
+            .into();
+        Ok(self)
+    }
+
     /// Generate a new Time-based OTP.
     ///
     /// # Examples
     ///
     /// ```
     /// use lugnut::totp::Totp;
-    /// let key = "my secret key".to_string();
+    /// let key = "my super secret key".to_string();
     /// let mut totp_builder = Totp::new();
     /// let code = totp_builder.generate(key);
     /// ```
-    pub fn generate<'a>(&'a self, key: String) -> std::result::Result<String, GenerationError> {
-        let counter = self.get_counter() as u128;
-        let hash = if self.digest.is_empty() {
-            digest(key.clone(), counter, Algorithm::Sha1)?
-        } else {
-            self.digest.clone()
-        };
-        generate_otp(6, hash)
+    pub fn generate(&self, key: impl AsRef<str>) -> core::result::Result<String, GenerationError> {
+        self.generate_for_time(key, self.get_time()?)
+    }
+
+    /// Generate a Time-based OTP for an explicit Unix timestamp instead of reading the clock.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The secret key
+    /// * `unix_time` - Seconds since the Unix epoch
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::totp::Totp;
+    /// let totp_builder = Totp::new();
+    /// let code = totp_builder.generate_for_time("my super secret key", 1_111_111_109);
+    /// ```
+    pub fn generate_for_time(
+        &self,
+        key: impl AsRef<str>,
+        unix_time: u64,
+    ) -> core::result::Result<String, GenerationError> {
+        let counter = self.counter_at(unix_time)?;
+        let hash = self.hash(key.as_ref(), counter)?;
+        generate_otp(self.digits, hash)
+    }
+
+    /// Generate a Time-based OTP for a `time::OffsetDateTime` or `chrono::DateTime`, with the
+    /// `time` or `chrono` feature enabled. See `generate_for_time`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The secret key
+    /// * `datetime` - The moment to generate the code for
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[cfg(feature = "time")] {
+    /// use time::{Date, Month};
+    /// use lugnut::totp::Totp;
+    /// let login = Date::from_calendar_date(2005, Month::March, 18)
+    ///     .unwrap()
+    ///     .with_hms(1, 58, 29)
+    ///     .unwrap()
+    ///     .assume_utc();
+    /// let totp_builder = Totp::new();
+    /// let code = totp_builder.generate_at_datetime("my super secret key", login);
+    /// # }
+    /// ```
+    #[cfg(any(feature = "time", feature = "chrono"))]
+    pub fn generate_at_datetime(
+        &self,
+        key: impl AsRef<str>,
+        datetime: impl ToUnixTime,
+    ) -> core::result::Result<String, GenerationError> {
+        self.generate_for_time(key, datetime.to_unix_time()?)
+    }
+
+    /// Generate a new Time-based OTP along with the number of seconds it remains valid for.
+    /// Both values are computed from a single clock read, so they can't disagree at a step boundary.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::totp::Totp;
+    /// let key = "my super secret key".to_string();
+    /// let totp_builder = Totp::new();
+    /// let code_and_seconds_remaining = totp_builder.generate_with_timing(key);
+    /// ```
+    pub fn generate_with_timing(
+        &self,
+        key: impl AsRef<str>,
+    ) -> core::result::Result<(String, u64), GenerationError> {
+        let time = self.get_time()?;
+        let counter = self.counter_at(time)?;
+        let hash = self.hash(key.as_ref(), counter)?;
+        let elapsed = time
+            .checked_sub(self.epoch_time_offset)
+            .ok_or(GenerationError::TimeBeforeEpoch())?
+            % self.step;
+        Ok((generate_otp(self.digits, hash)?, self.step - elapsed))
+    }
+
+    /// Returns every code `verify` would currently accept, oldest first. With a window of `n`
+    /// that is `2n + 1` codes, the middle one being the current code.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::totp::Totp;
+    /// let mut totp_builder = Totp::new();
+    /// totp_builder.with_window(1);
+    /// let codes = totp_builder.valid_codes("my super secret key");
+    /// ```
+    pub fn valid_codes(
+        &self,
+        key: impl AsRef<str>,
+    ) -> core::result::Result<Vec<String>, GenerationError> {
+        let counter = self.counter_at(self.get_time()?)?;
+        (counter.0.saturating_sub(self.window_back)..=counter.0.saturating_add(self.window_forward))
+            .map(|c| generate_otp(self.digits, self.hash(key.as_ref(), Counter(c))?))
+            .collect()
     }
 
     /// Verify a Time-based OTP.
@@ -117,68 +446,1148 @@ impl Totp {
     ///
     /// ```
     /// use lugnut::totp::Totp;
-    /// let key = "my secret key".to_string();
+    /// let key = "my super secret key".to_string();
     /// let mut totp_builder = Totp::new();
     /// let verified = totp_builder.verify("1234".to_string(), key);
     /// ```
-    pub fn verify<'a>(
-        &'a self,
-        token: String,
-        key: String,
-    ) -> std::result::Result<bool, GenerationError> {
-        let counter = self.get_counter();
-        let windowed_counter = (counter - self.window) as u128;
-        let hash = if self.digest.is_empty() {
-            digest(key.clone(), windowed_counter, Algorithm::Sha1)?
-        } else {
-            self.digest.clone()
-        };
-        verify_delta(
-            token,
-            windowed_counter,
-            6,
-            self.window + self.window,
-            hash,
+    pub fn verify(
+        &self,
+        token: impl AsRef<str>,
+        key: impl AsRef<str>,
+    ) -> core::result::Result<bool, GenerationError> {
+        self.verify_for_time(token, key, self.get_time()?)
+    }
+
+    /// Verify a Time-based OTP against an explicit Unix timestamp instead of reading the clock.
+    ///
+    /// Servers that already capture a timestamp per request (for logging, signing, etc.) should
+    /// read the clock once and pass the same value here, so every check made while handling the
+    /// request agrees on the current step and tests can pin the time without touching the `Totp`.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The code submitted by the user
+    /// * `key` - The secret key
+    /// * `unix_time` - Seconds since the Unix epoch
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::{SystemTime, UNIX_EPOCH};
+    /// use lugnut::totp::Totp;
+    /// let request_time = SystemTime::now()
+    ///     .duration_since(UNIX_EPOCH)
+    ///     .unwrap()
+    ///     .as_secs();
+    /// let totp_builder = Totp::new();
+    /// let verified = totp_builder.verify_for_time("123456", "my super secret key", request_time);
+    /// ```
+    pub fn verify_for_time(
+        &self,
+        token: impl AsRef<str>,
+        key: impl AsRef<str>,
+        unix_time: u64,
+    ) -> core::result::Result<bool, GenerationError> {
+        self.verify_with_algorithm(
+            token.as_ref(),
+            key.as_ref(),
+            unix_time,
+            self.window_back,
+            self.window_forward,
+            self.algorithm,
         )
     }
 
-    #[doc(hidden)]
-    fn get_counter<'a>(&'a self) -> u64 {
-        let end = if self.time == 0 {
-            SystemTime::now()
-        } else {
-            UNIX_EPOCH + Duration::from_secs(self.time)
-        };
+    /// Verify a Time-based OTP at a `time::OffsetDateTime` or `chrono::DateTime`, with the
+    /// `time` or `chrono` feature enabled. The configured window applies, see `verify_for_time`.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The code submitted by the user
+    /// * `key` - The secret key
+    /// * `datetime` - The moment to verify the code at
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[cfg(feature = "chrono")] {
+    /// use chrono::TimeZone;
+    /// use lugnut::totp::Totp;
+    /// let login = chrono::Utc.with_ymd_and_hms(2005, 3, 18, 1, 58, 29).unwrap();
+    /// let totp_builder = Totp::new();
+    /// let verified = totp_builder.verify_at_datetime("123456", "my super secret key", login);
+    /// # }
+    /// ```
+    #[cfg(any(feature = "time", feature = "chrono"))]
+    pub fn verify_at_datetime(
+        &self,
+        token: impl AsRef<str>,
+        key: impl AsRef<str>,
+        datetime: impl ToUnixTime,
+    ) -> core::result::Result<bool, GenerationError> {
+        self.verify_for_time(token, key, datetime.to_unix_time()?)
+    }
+
+    /// Verify a Time-based OTP, also accepting codes from the previous and next step to allow for
+    /// clock skew between client and server. This is equivalent to `verify` with a window of 1,
+    /// regardless of the configured window.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::totp::Totp;
+    /// let totp_builder = Totp::new();
+    /// let verified = totp_builder.verify_skewed("123456", "my super secret key");
+    /// ```
+    pub fn verify_skewed(
+        &self,
+        token: impl AsRef<str>,
+        key: impl AsRef<str>,
+    ) -> core::result::Result<bool, GenerationError> {
+        self.verify_with_algorithm(
+            token.as_ref(),
+            key.as_ref(),
+            self.get_time()?,
+            1,
+            1,
+            self.algorithm,
+        )
+    }
 
-        let start = UNIX_EPOCH + Duration::from_secs(self.epoch_time_offset);
+    /// Like `verify`, but returns the signed number of time steps between the step that matched
+    /// and the current one, or `None` if nothing in the window matched. A negative offset means
+    /// the code was from a past step, a positive one from a future step, so consistently non-zero
+    /// offsets point to clock skew on the client.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The code submitted by the user
+    /// * `key` - The secret key
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::totp::Totp;
+    /// let totp_builder = Totp::new();
+    /// let step = totp_builder.verify_with_step("123456", "my super secret key");
+    /// ```
+    pub fn verify_with_step(
+        &self,
+        token: impl AsRef<str>,
+        key: impl AsRef<str>,
+    ) -> core::result::Result<Option<i64>, GenerationError> {
+        find_delta(
+            token.as_ref(),
+            self.counter_at(self.get_time()?)?,
+            self.window_back,
+            self.window_forward,
+            self.digits,
+            |c| self.hash(key.as_ref(), c),
+        )
+    }
 
-        let epoch = end.duration_since(start).unwrap();
-        epoch.as_secs() / self.step
+    /// Verify a Time-based OTP against exactly the time step containing `unix_time`, ignoring
+    /// the window, e.g. to check a slot recorded by a replay protection store.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The code submitted by the user
+    /// * `key` - The secret key
+    /// * `unix_time` - Seconds since the Unix epoch
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::totp::Totp;
+    /// let totp_builder = Totp::new();
+    /// let verified = totp_builder.verify_at("123456", "my super secret key", 1_111_111_109);
+    /// ```
+    pub fn verify_at(
+        &self,
+        token: impl AsRef<str>,
+        key: impl AsRef<str>,
+        unix_time: u64,
+    ) -> core::result::Result<bool, GenerationError> {
+        self.verify_with_algorithm(
+            token.as_ref(),
+            key.as_ref(),
+            unix_time,
+            0,
+            0,
+            self.algorithm,
+        )
     }
-}
 
-#[cfg(test)]
-mod totp_tests {
-    use super::Totp;
-    use std::assert;
+    /// Verify a Time-based OTP that may have been generated with any of the given algorithms,
+    /// e.g. while migrating users from SHA-1 to SHA-256. Each algorithm is tried in order with
+    /// the configured window, stopping at the first match. The configured algorithm is ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The code submitted by the user
+    /// * `key` - The secret key
+    /// * `algorithms` - The algorithms to accept, in the order they are tried
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::{Algorithm, Totp};
+    /// let totp_builder = Totp::new();
+    /// let verified = totp_builder.verify_multi(
+    ///     "123456",
+    ///     "my super secret key",
+    ///     &[Algorithm::Sha1, Algorithm::Sha256],
+    /// );
+    /// ```
+    pub fn verify_multi(
+        &self,
+        token: impl AsRef<str>,
+        key: impl AsRef<str>,
+        algorithms: &[Algorithm],
+    ) -> core::result::Result<bool, GenerationError> {
+        self.verify_any_algorithm(token, key, algorithms)
+            .map(|algorithm| algorithm.is_some())
+    }
 
-    #[test]
-    fn assert_correct_otp() {
-        let key = "my secret key".to_string();
-        let totp = Totp::new();
-        let code = totp.generate(key.clone()).expect("borked");
-        let verified = totp.verify(code, key).expect("borked here too");
-        assert!(verified);
+    /// Like `verify_multi`, but returns the algorithm the token matched, or `None` if it matched
+    /// none of them, e.g. to record which algorithm a migrating user's authenticator uses.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The code submitted by the user
+    /// * `key` - The secret key
+    /// * `algorithms` - The algorithms to accept, in the order they are tried
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::{Algorithm, Totp};
+    /// let totp_builder = Totp::new();
+    /// let matched = totp_builder.verify_any_algorithm(
+    ///     "123456",
+    ///     "my super secret key",
+    ///     &[Algorithm::Sha1, Algorithm::Sha256],
+    /// );
+    /// ```
+    pub fn verify_any_algorithm(
+        &self,
+        token: impl AsRef<str>,
+        key: impl AsRef<str>,
+        algorithms: &[Algorithm],
+    ) -> core::result::Result<Option<Algorithm>, GenerationError> {
+        let time = self.get_time()?;
+        for algorithm in algorithms {
+            if self.verify_with_algorithm(
+                token.as_ref(),
+                key.as_ref(),
+                time,
+                self.window_back,
+                self.window_forward,
+                *algorithm,
+            )? {
+                return Ok(Some(*algorithm));
+            }
+        }
+        Ok(None)
     }
 
-    #[test]
-    fn assert_incorrect_otp() {
-        let key = "my secret key".to_string();
-        let totp = Totp::new();
-        let _code = totp.generate(key.clone()).expect("borked");
-        let verified = totp
-            .verify("wrong".to_string(), key)
-            .expect("borked here too");
-        assert!(!verified);
+    /// Verify a Time-based OTP at most once. The time step the token matched is recorded in
+    /// `guard`, and a token from that step or any earlier one is refused for `user` afterwards,
+    /// so an intercepted code can't be replayed while it is still valid.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The code submitted by the user
+    /// * `key` - The secret key
+    /// * `guard` - The store of consumed time steps
+    /// * `user` - The user the guard records the time step for
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// use lugnut::replay::MemoryReplayGuard;
+    /// use lugnut::totp::Totp;
+    /// let mut guard = MemoryReplayGuard::new();
+    /// let totp_builder = Totp::new();
+    /// let verified = totp_builder.verify_once("123456", "my super secret key", &mut guard, "alice");
+    /// # }
+    /// ```
+    pub fn verify_once(
+        &self,
+        token: impl AsRef<str>,
+        key: impl AsRef<str>,
+        guard: &mut impl ReplayGuard,
+        user: &str,
+    ) -> core::result::Result<bool, GenerationError> {
+        let counter = self.counter_at(self.get_time()?)?;
+        let matched = find_counter(
+            token.as_ref(),
+            counter.saturating_sub(self.window_back),
+            counter.saturating_add(self.window_forward),
+            self.digits,
+            |c| self.hash(key.as_ref(), c),
+        )?;
+        Ok(matched.is_some_and(|c| guard.check_and_consume(user, c.into())))
+    }
+
+    /// Verify a Time-based OTP that was stored as an integer, which drops leading zeros. The
+    /// code is zero padded to the configured length before comparing.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The code submitted by the user, as an integer
+    /// * `key` - The secret key
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::totp::Totp;
+    /// let totp_builder = Totp::new();
+    /// let verified = totp_builder.verify_numeric(12345, "my super secret key");
+    /// ```
+    pub fn verify_numeric(
+        &self,
+        code: u32,
+        key: impl AsRef<str>,
+    ) -> core::result::Result<bool, GenerationError> {
+        self.verify(numeric_token(code, self.digits), key)
+    }
+
+    /// Verify a Time-based OTP, reporting a token of the wrong length as
+    /// `GenerationError::TokenLengthMismatch` rather than a failed verification.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::{ GenerationError, Totp };
+    /// let totp_builder = Totp::new();
+    /// let result = totp_builder.verify_strict("1234", "my super secret key");
+    /// assert_eq!(result, Err(GenerationError::TokenLengthMismatch { expected: 6, got: 4 }));
+    /// ```
+    pub fn verify_strict(
+        &self,
+        token: impl AsRef<str>,
+        key: impl AsRef<str>,
+    ) -> core::result::Result<bool, GenerationError> {
+        check_token_length(token.as_ref(), self.digits)?;
+        self.verify(token, key)
+    }
+
+    /// Returns an otpauth URL Builder populated with this TOTP configuration, for when the
+    /// label style needs to be customized.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::totp::Totp;
+    /// let totp_builder = Totp::new();
+    /// let url = totp_builder
+    ///     .otpauth_url_builder()
+    ///     .with_issuer_in_label(false)
+    ///     .build("alice@example.com", "Example", "my super secret key");
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn otpauth_url_builder(&self) -> OtpAuthUrlBuilder {
+        let mut builder = OtpAuthUrlBuilder::new("totp");
+        builder
+            .with_parameter("algorithm", &self.algorithm.to_string())
+            .with_parameter("digits", &self.digits.to_string())
+            .with_parameter("period", &self.step.to_string());
+        builder
+    }
+
+    /// Build the otpauth Key URI used to enroll this TOTP configuration in an authenticator app.
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - The account name, e.g. `alice@example.com`
+    /// * `issuer` - The provider or service the account belongs to
+    /// * `secret` - The secret key, which is Base32 encoded into the URL
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::totp::Totp;
+    /// let totp_builder = Totp::new();
+    /// let url = totp_builder.to_otpauth_url("alice@example.com", "Example", "my super secret key");
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn to_otpauth_url(&self, label: &str, issuer: &str, secret: &str) -> String {
+        self.otpauth_url_builder().build(label, issuer, secret)
+    }
+
+    /// Build the otpauth Key URI using the secret stored with `with_secret_hex`, so the URL can't
+    /// be built with a different secret than codes are generated with. Fails with
+    /// `GenerationError::MissingSecret` if no secret is stored.
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - The account name, e.g. `alice@example.com`
+    /// * `issuer` - The provider or service the account belongs to
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::totp::Totp;
+    /// let mut totp_builder = Totp::new();
+    /// totp_builder
+    ///     .with_secret_hex("3132333435363738393031323334353637383930")
+    ///     .expect("invalid hex");
+    /// let url = totp_builder.otpauth_url_for("alice@example.com", "Example");
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn otpauth_url_for(
+        &self,
+        label: &str,
+        issuer: &str,
+    ) -> core::result::Result<String, GenerationError> {
+        if self.secret.is_empty() {
+            return Err(GenerationError::MissingSecret());
+        }
+        Ok(self
+            .otpauth_url_builder()
+            .build_from_bytes(label, issuer, self.secret.as_bytes()))
+    }
+
+    /// Render the otpauth Key URI as an SVG QR code. Requires the `qrcode` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lugnut::totp::Totp;
+    /// let totp_builder = Totp::new();
+    /// let svg = totp_builder.otpauth_qr_svg("alice@example.com", "Example", "my super secret key");
+    /// ```
+    #[cfg(feature = "qrcode")]
+    pub fn otpauth_qr_svg(
+        &self,
+        label: &str,
+        issuer: &str,
+        secret: &str,
+    ) -> core::result::Result<String, GenerationError> {
+        generate_qr_svg(&self.to_otpauth_url(label, issuer, secret))
+    }
+
+    #[doc(hidden)]
+    fn verify_with_algorithm(
+        &self,
+        token: &str,
+        key: &str,
+        unix_time: u64,
+        back: u64,
+        forward: u64,
+        algorithm: Algorithm,
+    ) -> core::result::Result<bool, GenerationError> {
+        let counter = self.counter_at(unix_time)?;
+        verify_delta(
+            token,
+            counter.saturating_sub(back),
+            counter.saturating_add(forward),
+            self.digits,
+            |c| self.hash_with(key, c, algorithm),
+        )
+    }
+
+    #[doc(hidden)]
+    fn hash(&self, key: &str, counter: Counter) -> core::result::Result<Vec<u8>, GenerationError> {
+        self.hash_with(key, counter, self.algorithm)
+    }
+
+    #[doc(hidden)]
+    fn hash_with(
+        &self,
+        key: &str,
+        counter: Counter,
+        algorithm: Algorithm,
+    ) -> core::result::Result<Vec<u8>, GenerationError> {
+        if !self.digest.is_empty() {
+            return Ok(self.digest.clone());
+        }
+        let key = if self.secret.is_empty() {
+            key.as_bytes()
+        } else {
+            self.secret.as_bytes()
+        };
+        check_key_length(key, self.min_key_length)?;
+        digest_bytes(key, counter, algorithm)
+    }
+
+    #[doc(hidden)]
+    fn get_time(&self) -> core::result::Result<u64, GenerationError> {
+        if self.time != 0 {
+            return Ok(self.time);
+        }
+
+        #[cfg(feature = "std")]
+        {
+            Ok(SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs())
+        }
+        #[cfg(not(feature = "std"))]
+        {
+            Err(GenerationError::MissingTime())
+        }
+    }
+
+    #[doc(hidden)]
+    fn counter_at(&self, time: u64) -> core::result::Result<Counter, GenerationError> {
+        Counter::from_unix_time(time, self.epoch_time_offset, self.step)
+    }
+}
+
+/// The moving factor is the Unix time in seconds, see `Totp::generate_for_time`.
+impl Otp for Totp {
+    fn generate(&self, key: &str, factor: u64) -> core::result::Result<String, GenerationError> {
+        self.generate_for_time(key, factor)
+    }
+
+    fn verify(
+        &self,
+        token: &str,
+        key: &str,
+        factor: u64,
+    ) -> core::result::Result<bool, GenerationError> {
+        self.verify_for_time(token, key, factor)
+    }
+}
+
+impl Default for Totp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A TOTP Builder that takes and returns itself by value, so configuration can be chained
+/// straight off of `Totp::builder()`. Each setter mirrors the `with_` setter on `Totp`.
+pub struct TotpBuilder {
+    totp: Totp,
+}
+
+impl TotpBuilder {
+    /// See `Totp::with_epoch_time_offset`.
+    pub fn epoch_time_offset(mut self, offset: u64) -> TotpBuilder {
+        self.totp.with_epoch_time_offset(offset);
+        self
+    }
+
+    /// See `Totp::with_time`.
+    pub fn time(mut self, time: u64) -> TotpBuilder {
+        self.totp.with_time(time);
+        self
+    }
+
+    /// See `Totp::with_step`.
+    pub fn step(mut self, step: u64) -> TotpBuilder {
+        self.totp.with_step(step);
+        self
+    }
+
+    /// See `Totp::with_length`.
+    pub fn length(mut self, digits: u32) -> TotpBuilder {
+        self.totp.with_length(digits);
+        self
+    }
+
+    /// See `Totp::with_algorithm`.
+    pub fn algorithm(mut self, algorithm: Algorithm) -> TotpBuilder {
+        self.totp.with_algorithm(algorithm);
+        self
+    }
+
+    /// See `Totp::with_window`.
+    pub fn window(mut self, window: u64) -> TotpBuilder {
+        self.totp.with_window(window);
+        self
+    }
+
+    /// See `Totp::with_window_asymmetric`.
+    pub fn window_asymmetric(mut self, back: u64, forward: u64) -> TotpBuilder {
+        self.totp.with_window_asymmetric(back, forward);
+        self
+    }
+
+    /// See `Totp::with_digest`.
+    pub fn digest(mut self, digest: Vec<u8>) -> TotpBuilder {
+        self.totp.with_digest(digest);
+        self
+    }
+
+    /// See `Totp::with_min_key_length`.
+    pub fn min_key_length(mut self, min: usize) -> TotpBuilder {
+        self.totp.with_min_key_length(min);
+        self
+    }
+
+    /// See `Totp::with_secret_hex`.
+    pub fn secret_hex(mut self, hex: &str) -> core::result::Result<TotpBuilder, GenerationError> {
+        self.totp.with_secret_hex(hex)?;
+        Ok(self)
+    }
+
+    /// Finish configuring and return the `Totp`.
+    pub fn build(self) -> Totp {
+        self.totp
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod totp_tests {
+    use super::Totp;
+    use std::assert;
+
+    #[test]
+    fn assert_correct_otp() {
+        let key = "my super secret key".to_string();
+        let totp = Totp::new();
+        let code = totp.generate(key.clone()).expect("borked");
+        let verified = totp.verify(code, key).expect("borked here too");
+        assert!(verified);
+    }
+
+    #[test]
+    fn assert_incorrect_otp() {
+        let key = "my super secret key".to_string();
+        let totp = Totp::new();
+        let _code = totp.generate(key.clone()).expect("borked");
+        let verified = totp.verify("wrong", key).expect("borked here too");
+        assert!(!verified);
+    }
+
+    #[test]
+    fn assert_verify_with_str() {
+        let totp = Totp::new();
+        let code = totp.generate("my super secret key").expect("borked");
+        let verified = totp
+            .verify(code.as_str(), "my super secret key")
+            .expect("borked here too");
+        assert!(verified);
+        assert!(!totp
+            .verify("000000x", "my super secret key")
+            .expect("borked"));
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod totp_otpauth_tests {
+    use super::Totp;
+    use crate::GenerationError;
+
+    #[test]
+    fn assert_otpauth_url() {
+        let totp = Totp::new();
+        assert_eq!(
+            totp.to_otpauth_url("alice@example.com", "Example", "12345678901234567890"),
+            "otpauth://totp/Example:alice%40example.com?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ\
+             &issuer=Example&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+
+    #[test]
+    fn assert_otpauth_url_for_stored_secret() {
+        let mut totp = Totp::new();
+        assert_eq!(
+            totp.otpauth_url_for("alice@example.com", "Example"),
+            Err(GenerationError::MissingSecret())
+        );
+        totp.with_secret_hex("3132333435363738393031323334353637383930")
+            .expect("valid hex");
+        assert_eq!(
+            totp.otpauth_url_for("alice@example.com", "Example"),
+            Ok(totp.to_otpauth_url("alice@example.com", "Example", "12345678901234567890"))
+        );
+    }
+
+    #[cfg(feature = "qrcode")]
+    #[test]
+    fn assert_otpauth_qr_svg() {
+        let totp = Totp::new();
+        let svg = totp
+            .otpauth_qr_svg("alice@example.com", "Example", "my super secret key")
+            .expect("failed to render qr code");
+        assert!(!svg.is_empty());
+        assert!(svg.contains("<svg"));
+    }
+}
+
+#[cfg(test)]
+mod totp_timing_tests {
+    use super::Totp;
+
+    #[test]
+    fn assert_generate_with_timing() {
+        let mut totp = Totp::new();
+        totp.with_secret_hex("3132333435363738393031323334353637383930")
+            .expect("valid hex");
+
+        // RFC 6238 Appendix B, T = 1111111109 is 29 seconds into its step
+        totp.with_time(1_111_111_109);
+        let (code, remaining) = totp.generate_with_timing("").expect("borked");
+        assert_eq!(code, "081804");
+        assert_eq!(remaining, 1);
+        assert_eq!(code, totp.generate("").expect("borked"));
+
+        // The first second of the next step has the full step remaining
+        totp.with_time(1_111_111_110);
+        let (code, remaining) = totp.generate_with_timing("").expect("borked");
+        assert_eq!(code, "050471");
+        assert_eq!(remaining, 30);
+    }
+
+    #[test]
+    fn assert_verify_at_is_exact() {
+        let key = "12345678901234567890";
+        let mut totp = Totp::new();
+        totp.with_window(2);
+        let code = totp.generate_for_time(key, 59).expect("borked");
+        assert!(totp.verify_at(&code, key, 59).expect("borked"));
+        assert!(totp.verify_at(&code, key, 30).expect("borked"));
+        assert!(!totp.verify_at(&code, key, 29).expect("borked"));
+        assert!(!totp.verify_at(&code, key, 60).expect("borked"));
+        assert!(totp.verify_for_time(&code, key, 60).expect("borked"));
+    }
+
+    #[test]
+    fn assert_asymmetric_window() {
+        let key = "12345678901234567890";
+        let mut totp = Totp::new();
+        totp.with_window_asymmetric(2, 0);
+        let past = totp.generate_for_time(key, 59).expect("borked");
+        let future = totp.generate_for_time(key, 149).expect("borked");
+        assert!(totp.verify_for_time(&past, key, 119).expect("borked"));
+        assert!(!totp.verify_for_time(&future, key, 119).expect("borked"));
+
+        totp.with_window(2);
+        assert!(totp.verify_for_time(&future, key, 119).expect("borked"));
+    }
+
+    #[test]
+    fn assert_verify_for_captured_time() {
+        let mut totp = Totp::new();
+        totp.with_secret_hex("3132333435363738393031323334353637383930")
+            .expect("valid hex");
+
+        // A handler captures the request time once and threads it through
+        let request_time = 1_111_111_109;
+        let code = totp.generate_for_time("", request_time).expect("borked");
+        assert_eq!(code, "081804");
+        assert!(totp
+            .verify_for_time(&code, "", request_time)
+            .expect("borked"));
+        assert!(!totp
+            .verify_for_time(&code, "", request_time + 30)
+            .expect("borked"));
+
+        // With a window, the previous step is still accepted
+        totp.with_window(1);
+        assert!(totp
+            .verify_for_time(&code, "", request_time + 30)
+            .expect("borked"));
+    }
+}
+
+#[cfg(test)]
+mod totp_step_tests {
+    use super::Totp;
+    use crate::GenerationError;
+
+    #[test]
+    fn assert_zero_step_errors() {
+        let key = "12345678901234567890";
+        let mut totp = Totp::new();
+        totp.with_time(59).with_step(0);
+        assert_eq!(totp.generate(key), Err(GenerationError::InvalidPeriod()));
+        assert_eq!(
+            totp.generate_with_timing(key),
+            Err(GenerationError::InvalidPeriod())
+        );
+        assert_eq!(
+            totp.verify("287082", key),
+            Err(GenerationError::InvalidPeriod())
+        );
+
+        totp.with_step(30);
+        assert_eq!(totp.generate(key), Ok("287082".into()));
+    }
+
+    #[test]
+    fn assert_time_before_epoch_errors() {
+        let key = "12345678901234567890";
+        let mut totp = Totp::new();
+        totp.with_epoch_time_offset(500);
+        assert_eq!(
+            totp.generate_for_time(key, 100),
+            Err(GenerationError::TimeBeforeEpoch())
+        );
+        assert_eq!(
+            totp.verify_for_time("287082", key, 100),
+            Err(GenerationError::TimeBeforeEpoch())
+        );
+        assert_eq!(
+            totp.verify_at("287082", key, 499),
+            Err(GenerationError::TimeBeforeEpoch())
+        );
+        assert!(totp.generate_for_time(key, 500).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod totp_min_key_length_tests {
+    use super::Totp;
+    use crate::GenerationError;
+
+    #[test]
+    fn assert_short_key_errors_by_default() {
+        let mut totp = Totp::new();
+        totp.with_time(59);
+        assert_eq!(
+            totp.generate("abcd"),
+            Err(GenerationError::KeyTooShort { got: 4, min: 16 })
+        );
+        totp.with_min_key_length(4);
+        assert!(totp.generate("abcd").is_ok());
+    }
+
+    #[test]
+    fn assert_empty_key_errors() {
+        let mut totp = Totp::new();
+        totp.with_time(59).with_min_key_length(0);
+        assert_eq!(totp.generate(""), Err(GenerationError::EmptyKey()));
+        assert_eq!(totp.verify("123456", ""), Err(GenerationError::EmptyKey()));
+    }
+}
+
+#[cfg(test)]
+mod totp_builder_tests {
+    use super::Totp;
+
+    #[test]
+    fn assert_builder_matches_setters() {
+        let built = Totp::builder().step(60).time(1_111_111_109).build();
+        let mut configured = Totp::new();
+        configured.with_step(60).with_time(1_111_111_109);
+        assert_eq!(
+            built.generate("my super secret key"),
+            configured.generate("my super secret key")
+        );
+    }
+}
+
+#[cfg(test)]
+mod totp_rfc6238_tests {
+    use super::Totp;
+    use crate::Algorithm;
+
+    // RFC 6238 Appendix B uses a seed of the ASCII digits repeated out to the hash output length
+    const SEED_SHA1: &str = "12345678901234567890";
+    const SEED_SHA256: &str = "12345678901234567890123456789012";
+    const SEED_SHA512: &str = "1234567890123456789012345678901234567890123456789012345678901234";
+
+    fn assert_vectors(algorithm: Algorithm, seed: &str, expected: &[(u64, &str)]) {
+        for (time, code) in expected {
+            let mut totp = Totp::rfc6238(algorithm, 8, 30, 0);
+            totp.with_time(*time);
+            assert_eq!(totp.generate(seed).expect("borked"), *code);
+        }
+    }
+
+    #[test]
+    fn assert_rfc6238_sha1() {
+        assert_vectors(
+            Algorithm::Sha1,
+            SEED_SHA1,
+            &[
+                (59, "94287082"),
+                (1_111_111_109, "07081804"),
+                (20_000_000_000, "65353130"),
+            ],
+        );
+    }
+
+    #[test]
+    fn assert_rfc6238_sha256() {
+        assert_vectors(
+            Algorithm::Sha256,
+            SEED_SHA256,
+            &[
+                (59, "46119246"),
+                (1_111_111_109, "68084774"),
+                (20_000_000_000, "77737706"),
+            ],
+        );
+    }
+
+    #[test]
+    fn assert_rfc6238_sha512() {
+        assert_vectors(
+            Algorithm::Sha512,
+            SEED_SHA512,
+            &[
+                (59, "90693936"),
+                (1_111_111_109, "25091201"),
+                (20_000_000_000, "47863826"),
+            ],
+        );
+    }
+
+    #[test]
+    fn assert_rfc6238_builder() {
+        let totp = Totp::builder()
+            .algorithm(Algorithm::Sha512)
+            .length(8)
+            .step(30)
+            .epoch_time_offset(0)
+            .time(1_234_567_890)
+            .build();
+        assert_eq!(totp.generate(SEED_SHA512).expect("borked"), "93441116");
+    }
+}
+
+#[cfg(test)]
+mod totp_verify_skewed_tests {
+    use super::Totp;
+
+    #[test]
+    fn assert_verify_skewed_accepts_adjacent_steps() {
+        let key = "12345678901234567890";
+        let mut totp = Totp::new();
+        totp.with_time(59);
+        let code = totp.generate(key).expect("borked");
+
+        totp.with_time(89);
+        assert!(!totp.verify(&code, key).expect("borked"));
+        assert!(totp.verify_skewed(&code, key).expect("borked"));
+
+        totp.with_time(29);
+        assert!(totp.verify_skewed(&code, key).expect("borked"));
+
+        totp.with_time(119);
+        assert!(!totp.verify_skewed(&code, key).expect("borked"));
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod totp_verify_once_tests {
+    use super::Totp;
+    use crate::replay::MemoryReplayGuard;
+
+    #[test]
+    fn assert_code_cannot_be_verified_twice() {
+        let key = "12345678901234567890";
+        let mut guard = MemoryReplayGuard::new();
+        let mut totp = Totp::new();
+        totp.with_time(59).with_window(1);
+        let code = totp.generate(key).expect("borked");
+
+        assert!(totp
+            .verify_once(&code, key, &mut guard, "alice")
+            .expect("borked"));
+        assert!(!totp
+            .verify_once(&code, key, &mut guard, "alice")
+            .expect("borked"));
+        // Still valid in the next step thanks to the window, but already consumed
+        totp.with_time(89);
+        assert!(!totp
+            .verify_once(&code, key, &mut guard, "alice")
+            .expect("borked"));
+        assert!(totp.verify(&code, key).expect("borked"));
+        // Consumption is per user
+        assert!(totp
+            .verify_once(&code, key, &mut guard, "bob")
+            .expect("borked"));
+    }
+}
+
+#[cfg(test)]
+mod totp_valid_codes_tests {
+    use super::Totp;
+    use alloc::string::String;
+
+    #[test]
+    fn assert_valid_codes_cover_the_window() {
+        let key = "12345678901234567890";
+        let mut totp = Totp::new();
+        totp.with_time(89).with_window(1);
+        let codes = totp.valid_codes(key).expect("borked");
+        assert_eq!(codes.len(), 3);
+        assert_eq!(codes[1], totp.generate(key).expect("borked"));
+        // RFC 6238 T = 59 is the previous step
+        assert_eq!(codes[0], "287082");
+        for code in &codes {
+            assert!(totp.verify(code, key).expect("borked"));
+        }
+
+        totp.with_window(0);
+        assert_eq!(totp.valid_codes(key).expect("borked").len(), 1);
+        assert_eq!(totp.valid_codes(String::from(key)), totp.valid_codes(key));
+    }
+}
+
+#[cfg(test)]
+mod totp_verify_multi_tests {
+    use super::Totp;
+    use crate::Algorithm;
+
+    #[test]
+    fn assert_verify_multi_accepts_any_listed_algorithm() {
+        let key = "12345678901234567890123456789012";
+        let mut totp = Totp::new();
+        totp.with_time(59).with_algorithm(Algorithm::Sha256);
+        let code = totp.generate(key).expect("borked");
+
+        totp.with_algorithm(Algorithm::Sha1);
+        assert!(!totp.verify(&code, key).expect("borked"));
+        assert!(totp
+            .verify_multi(&code, key, &[Algorithm::Sha1, Algorithm::Sha256])
+            .expect("borked"));
+        assert!(!totp
+            .verify_multi(&code, key, &[Algorithm::Sha1, Algorithm::Sha512])
+            .expect("borked"));
+    }
+
+    #[test]
+    fn assert_verify_any_algorithm_reports_match() {
+        let key = "12345678901234567890123456789012";
+        let mut totp = Totp::new();
+        totp.with_time(59).with_algorithm(Algorithm::Sha256);
+        let code = totp.generate(key).expect("borked");
+
+        let algorithms = [Algorithm::Sha1, Algorithm::Sha256, Algorithm::Sha512];
+        assert_eq!(
+            totp.verify_any_algorithm(&code, key, &algorithms),
+            Ok(Some(Algorithm::Sha256))
+        );
+        assert_eq!(
+            totp.verify_any_algorithm("garbage", key, &algorithms),
+            Ok(None)
+        );
+    }
+}
+
+#[cfg(test)]
+mod totp_verify_strict_tests {
+    use super::Totp;
+    use crate::GenerationError;
+
+    #[test]
+    fn assert_verify_strict() {
+        let mut totp = Totp::new();
+        totp.with_time(1_111_111_109);
+        let code = totp.generate("my super secret key").expect("borked");
+        assert_eq!(totp.verify("1234", "my super secret key"), Ok(false));
+        assert_eq!(
+            totp.verify_strict("1234", "my super secret key"),
+            Err(GenerationError::TokenLengthMismatch {
+                expected: 6,
+                got: 4
+            })
+        );
+        assert_eq!(totp.verify_strict(&code, "my super secret key"), Ok(true));
+    }
+}
+
+#[cfg(all(test, feature = "time"))]
+mod totp_time_tests {
+    use super::Totp;
+    use crate::{Algorithm, GenerationError};
+    use time::{Date, Month, OffsetDateTime};
+
+    #[test]
+    fn assert_generate_at_datetime() {
+        // 2005-03-18 01:58:29 UTC, 1111111109 in RFC 6238 Appendix B
+        let datetime = Date::from_calendar_date(2005, Month::March, 18)
+            .unwrap()
+            .with_hms(1, 58, 29)
+            .unwrap()
+            .assume_utc();
+        let totp = Totp::rfc6238(Algorithm::Sha1, 8, 30, 0);
+        let code = totp
+            .generate_at_datetime("12345678901234567890", datetime)
+            .expect("borked");
+        assert_eq!(code, "07081804");
+        assert!(totp
+            .verify_at_datetime(&code, "12345678901234567890", datetime)
+            .expect("borked"));
+    }
+
+    #[test]
+    fn assert_datetime_before_epoch() {
+        let totp = Totp::new();
+        let datetime = OffsetDateTime::UNIX_EPOCH - time::Duration::seconds(1);
+        assert_eq!(
+            totp.generate_at_datetime("12345678901234567890", datetime),
+            Err(GenerationError::TimeBeforeEpoch())
+        );
+    }
+}
+
+#[cfg(all(test, feature = "chrono"))]
+mod totp_chrono_tests {
+    use super::Totp;
+    use crate::{Algorithm, GenerationError};
+    use chrono::{FixedOffset, TimeZone, Utc};
+
+    #[test]
+    fn assert_generate_at_datetime() {
+        // 2005-03-18 01:58:29 UTC, 1111111109 in RFC 6238 Appendix B
+        let datetime = Utc.with_ymd_and_hms(2005, 3, 18, 1, 58, 29).unwrap();
+        let totp = Totp::rfc6238(Algorithm::Sha1, 8, 30, 0);
+        let code = totp
+            .generate_at_datetime("12345678901234567890", datetime)
+            .expect("borked");
+        assert_eq!(code, "07081804");
+
+        // The same moment in another time zone
+        let local = datetime.with_timezone(&FixedOffset::east_opt(3600).unwrap());
+        assert!(totp
+            .verify_at_datetime(&code, "12345678901234567890", local)
+            .expect("borked"));
+    }
+
+    #[test]
+    fn assert_datetime_before_epoch() {
+        let totp = Totp::new();
+        let datetime = Utc.with_ymd_and_hms(1969, 12, 31, 23, 59, 59).unwrap();
+        assert_eq!(
+            totp.generate_at_datetime("12345678901234567890", datetime),
+            Err(GenerationError::TimeBeforeEpoch())
+        );
+    }
+}
+
+#[cfg(test)]
+mod totp_verify_numeric_tests {
+    use super::Totp;
+
+    #[test]
+    fn assert_verify_numeric_pads_leading_zeros() {
+        let key = "12345678901234567890";
+        let mut totp = Totp::new();
+        totp.with_time(900);
+        assert_eq!(totp.generate(key).expect("borked"), "026920");
+        assert!(!totp.verify("26920", key).expect("borked"));
+        assert!(totp.verify_numeric(26920, key).expect("borked"));
+        assert!(!totp.verify_numeric(26921, key).expect("borked"));
+    }
+}
+
+#[cfg(test)]
+mod totp_verify_with_step_tests {
+    use super::Totp;
+
+    #[test]
+    fn assert_verify_with_step_reports_offset() {
+        let key = "12345678901234567890";
+        let mut totp = Totp::new();
+        totp.with_window(3);
+        let future = totp.generate_for_time(key, 119).expect("borked");
+        let past = totp.generate_for_time(key, 29).expect("borked");
+
+        totp.with_time(59);
+        assert_eq!(totp.verify_with_step(&future, key), Ok(Some(2)));
+        assert_eq!(totp.verify_with_step(&past, key), Ok(Some(-1)));
+        assert_eq!(totp.verify_with_step("000000", key), Ok(None));
     }
 }